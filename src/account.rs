@@ -3,12 +3,19 @@ use rust_decimal::Decimal;
 use serde::ser::SerializeStruct;
 use serde::ser::{Serialize, Serializer};
 
+use crate::{
+    journal::{Book, Journal, TrialBalance},
+    lifecycle::{AccountChange, Effect, Transition},
+};
+
+/// Client balances are projections of a double-entry journal: every operation posts a balanced
+/// entry between the client's available, held and external books.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Account {
     id: u16,
-    available: Decimal,
-    held: Decimal,
+    journal: Journal,
     locked: bool,
+    closed: bool,
 }
 
 impl Serialize for Account {
@@ -16,13 +23,14 @@ impl Serialize for Account {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("Account", 5)?;
+        let mut state = serializer.serialize_struct("Account", 6)?;
 
         state.serialize_field("client", &self.id)?;
-        state.serialize_field("available", &self.available.to_string())?;
+        state.serialize_field("available", &self.available().to_string())?;
         state.serialize_field("held", &self.held().to_string())?;
         state.serialize_field("total", &self.total().to_string())?;
         state.serialize_field("locked", &self.locked.to_string())?;
+        state.serialize_field("closed", &self.closed.to_string())?;
 
         state.end()
     }
@@ -32,65 +40,151 @@ impl Account {
     pub fn new(id: u16) -> Self {
         Account {
             id,
-            available: dec!(0.0),
-            held: dec!(0.0),
+            journal: Journal::default(),
             locked: false,
+            closed: false,
+        }
+    }
+
+    /// Restores an account from the trial balance of its journal.
+    pub fn from_trial_balance(
+        id: u16,
+        trial_balance: TrialBalance,
+        locked: bool,
+        closed: bool,
+    ) -> Self {
+        Account {
+            id,
+            journal: Journal::from_trial_balance(trial_balance),
+            locked,
+            closed,
         }
     }
 
+    pub fn client(&self) -> u16 {
+        self.id
+    }
+
     pub fn held(&self) -> Decimal {
-        self.held
+        self.journal.balance(Book::Held)
     }
 
+    /// Negative amounts dispute a deposit, positive ones a withdrawal.
     pub fn hold(&mut self, amount: Decimal) {
-        if amount < dec!(0) {
-            self.held += amount * dec!(-1);
-            self.available += amount;
-        } else {
-            self.held += amount;
-        }
+        self.journal
+            .post(Book::Held, Self::disputed_from(amount), amount.abs());
     }
 
     pub fn resolve(&mut self, amount: Decimal) {
-        if amount < dec!(0) {
-            self.held -= amount * dec!(-1);
-        } else {
-            self.held -= amount;
-            self.available += amount;
-        }
+        self.journal
+            .post(Self::reversed_to(amount), Book::Held, amount.abs());
     }
 
     pub fn reject(&mut self, amount: Decimal) {
-        if amount < dec!(0) {
-            self.held -= amount * dec!(-1);
-            self.available += amount * dec!(-1);
-        } else {
-            self.held -= amount;
+        self.journal
+            .post(Self::disputed_from(amount), Book::Held, amount.abs());
+    }
+
+    /// Reverses a chargeback: the funds it returned move as a resolve would have moved them.
+    pub fn restore(&mut self, amount: Decimal) {
+        self.journal.post(
+            Self::reversed_to(amount),
+            Self::disputed_from(amount),
+            amount.abs(),
+        );
+    }
+
+    /// Applies the balance effect and account change of a dispute transition.
+    pub fn apply(&mut self, transition: &Transition, amount: Decimal) {
+        match transition.effect {
+            Effect::Hold => self.hold(amount),
+            Effect::Resolve => self.resolve(amount),
+            Effect::Reject => self.reject(amount),
+            Effect::Restore => self.restore(amount),
+            Effect::None => {}
+        }
+        match transition.account {
+            Some(AccountChange::Lock) => self.lock(),
+            Some(AccountChange::Unlock) => self.unlock(),
+            None => {}
         }
-        self.lock();
     }
 
     pub fn deposit(&mut self, amount: Decimal) {
-        self.available += amount
+        self.journal.post(Book::Available, Book::External, amount)
     }
 
     pub fn withdraw(&mut self, amount: Decimal) {
-        self.available -= amount
+        self.journal.post(Book::External, Book::Available, amount)
     }
 
     pub fn available(&self) -> Decimal {
-        self.available
+        self.journal.balance(Book::Available)
     }
 
     pub fn total(&self) -> Decimal {
-        self.held + self.available
+        self.held() + self.available()
+    }
+
+    pub fn trial_balance(&self) -> &TrialBalance {
+        self.journal.trial_balance()
     }
 
     pub fn lock(&mut self) {
         self.locked = true;
     }
 
+    pub fn unlock(&mut self) {
+        self.locked = false;
+    }
+
     pub fn locked(&self) -> bool {
         self.locked
     }
+
+    /// The available funds closing the account would sweep to the payout. A locked account
+    /// keeps its funds until the lock is lifted.
+    pub fn payout(&self) -> Result<Decimal, String> {
+        if self.locked {
+            return Err(format!("Account {} is locked", self.id));
+        }
+        if self.held() != dec!(0) {
+            return Err(format!("Account {} has open disputes", self.id));
+        }
+        if self.available() < dec!(0) {
+            return Err(format!("Account {} has a negative balance", self.id));
+        }
+        Ok(self.available())
+    }
+
+    /// Closes the account and returns the available funds swept to the payout.
+    pub fn close(&mut self) -> Result<Decimal, String> {
+        let payout = self.payout()?;
+        self.withdraw(payout);
+        self.closed = true;
+        Ok(payout)
+    }
+
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+
+    /// A disputed deposit is held out of the available funds, a disputed withdrawal out of the
+    /// funds that already left the engine.
+    fn disputed_from(amount: Decimal) -> Book {
+        if amount < dec!(0) {
+            Book::Available
+        } else {
+            Book::External
+        }
+    }
+
+    /// Resolving a dispute reverses the original transaction.
+    fn reversed_to(amount: Decimal) -> Book {
+        if amount < dec!(0) {
+            Book::External
+        } else {
+            Book::Available
+        }
+    }
 }
@@ -0,0 +1,235 @@
+use csv::Writer;
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+
+use crate::{
+    engine::Engine,
+    event::{Event, EventType},
+    export,
+    listener::{answer_lines, POLL_INTERVAL},
+};
+
+/// Takes operator commands on a Unix socket at `path` until `stop` is set, serving every
+/// connection on its own thread. `shutdown` sets `stop` itself, so the caller drains the engine
+/// as it would on a signal.
+pub fn serve(path: &str, engine: &Engine, stop: &AtomicBool) -> Result<(), String> {
+    // A socket left behind by an earlier run would make the bind fail.
+    remove_socket(path);
+    let listener = UnixListener::bind(path)
+        .map_err(|e| format!("Can't bind the admin socket {}: {}", path, e))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Can't poll the admin socket: {}", e))?;
+    eprintln!("Admin socket at {}", path);
+
+    thread::scope(|scope| {
+        while !stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    scope.spawn(move || {
+                        if let Err(e) = handle(stream, engine, stop) {
+                            eprintln!("Admin connection failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(e) => eprintln!("Can't accept an admin connection: {}", e),
+            }
+        }
+    });
+    remove_socket(path);
+    Ok(())
+}
+
+/// Removes what's at `path` only if it's a socket, so a mistyped path can't delete a file.
+fn remove_socket(path: &str) {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Every command is answered with its output followed by `ok`, or with `error` and the reason.
+fn handle(stream: UnixStream, engine: &Engine, stop: &AtomicBool) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let writer = stream.try_clone()?;
+    answer_lines(stream, writer, stop, |line| {
+        if line.is_empty() {
+            return None;
+        }
+        Some(match execute(engine, stop, line) {
+            Ok(output) => format!("{}ok", output),
+            Err(reason) => format!("error {}", reason),
+        })
+    })
+}
+
+fn execute(engine: &Engine, stop: &AtomicBool, line: &str) -> Result<String, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["status"] => Ok(status(engine)),
+        ["snapshot", path] => {
+            let core = &engine.core;
+            export::sqlite(path, &core.ledger, core.chart.as_ref()).map(|_| String::new())
+        }
+        ["dump-accounts"] => dump_accounts(engine),
+        ["lock", client] => lock(engine, client).map(|_| String::new()),
+        ["shutdown"] => {
+            stop.store(true, Ordering::Relaxed);
+            Ok(String::new())
+        }
+        _ => Err(format!("Unknown command {}", line.trim())),
+    }
+}
+
+fn status(engine: &Engine) -> String {
+    engine
+        .status()
+        .iter()
+        .map(|worker| {
+            format!(
+                "worker {}: queued {}, processed {}\n",
+                worker.worker, worker.queued, worker.processed
+            )
+        })
+        .collect()
+}
+
+fn dump_accounts(engine: &Engine) -> Result<String, String> {
+    let mut wtr = Writer::from_writer(Vec::new());
+    for account in engine.core.chart.accounts() {
+        wtr.serialize(account)
+            .map_err(|e| format!("Can't write the accounts: {}", e))?;
+    }
+    let bytes = wtr
+        .into_inner()
+        .map_err(|e| format!("Can't write the accounts: {}", e))?;
+    String::from_utf8(bytes).map_err(|e| format!("Can't write the accounts: {}", e))
+}
+
+/// Locks the account as a chargeback would: it still takes deposits but no withdrawals. The
+/// lock goes through the client's worker like any event, so the ledger records it in order.
+fn lock(engine: &Engine, client: &str) -> Result<(), String> {
+    let Ok(client) = client.parse::<u16>() else {
+        return Err(format!("Invalid client {}", client));
+    };
+    // An operator lock names no transaction.
+    let event = Event {
+        tx_type: EventType::Lock,
+        client,
+        tx: 0,
+        amount: None,
+        reason: None,
+        case_id: None,
+        notes: None,
+        sequence: 0,
+    };
+    engine.submit_tracked(event)?.wait().result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ledger::Ledger, store::MemoryChart, verify};
+    use std::{
+        io::{BufRead, BufReader, Write},
+        sync::Arc,
+        time::Duration,
+    };
+
+    #[test]
+    fn test_admin_commands() {
+        let mut engine = Engine::new(Ledger::default(), Arc::new(MemoryChart::default()));
+        let handles = engine.start_workers();
+        for event in [Event::deposit(1, 1, 10.0), Event::deposit(2, 2, 5.0)] {
+            engine.submit_event(event).unwrap();
+        }
+        while engine.status().iter().map(|w| w.processed).sum::<u64>() < 2 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let stop = AtomicBool::new(false);
+
+        assert_eq!(
+            execute(&engine, &stop, "status"),
+            Ok(String::from(
+                "worker 0: queued 0, processed 0\n\
+                 worker 1: queued 0, processed 1\n\
+                 worker 2: queued 0, processed 1\n\
+                 worker 3: queued 0, processed 0\n"
+            ))
+        );
+        assert_eq!(execute(&engine, &stop, "lock 2"), Ok(String::new()));
+        assert_eq!(
+            execute(&engine, &stop, "lock 3"),
+            Err(String::from("Can't find an account for client 3"))
+        );
+        assert_eq!(
+            execute(&engine, &stop, "dump-accounts"),
+            Ok(String::from(
+                "client,available,held,total,locked,closed\n\
+                 1,10,0,10,false,false\n\
+                 2,5,0,5,true,false\n"
+            ))
+        );
+        assert!(execute(&engine, &stop, "reboot").is_err());
+        assert_eq!(
+            verify::reconcile(&engine.core.ledger, engine.core.chart.as_ref()),
+            vec![]
+        );
+        assert!(engine.core.ledger.rollup(2).locked);
+
+        assert_eq!(execute(&engine, &stop, "shutdown"), Ok(String::new()));
+        assert!(stop.load(Ordering::Relaxed));
+        engine.shutdown();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_keep_other_files_at_the_path() {
+        let path = std::env::temp_dir().join(format!("admin-{}.txt", std::process::id()));
+        fs::write(&path, "keep").unwrap();
+        let engine = Engine::new(Ledger::default(), Arc::new(MemoryChart::default()));
+
+        let path = path.to_str().unwrap();
+        assert!(serve(path, &engine, &AtomicBool::new(true)).is_err());
+        assert_eq!(fs::read_to_string(path).unwrap(), "keep");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_idle_connection_does_not_block_others() {
+        let path = std::env::temp_dir().join(format!("admin-{}.sock", std::process::id()));
+        let path = path.to_str().unwrap();
+        let engine = Engine::new(Ledger::default(), Arc::new(MemoryChart::default()));
+        let stop = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            let server = scope.spawn(|| serve(path, &engine, &stop));
+            let connect = || loop {
+                match UnixStream::connect(path) {
+                    Ok(stream) => break stream,
+                    Err(_) => thread::sleep(Duration::from_millis(10)),
+                }
+            };
+            let _idle = connect();
+            let mut active = connect();
+            active.write_all(b"shutdown\n").unwrap();
+            let mut reply = String::new();
+            BufReader::new(active).read_line(&mut reply).unwrap();
+
+            assert_eq!(reply, "ok\n");
+            assert!(server.join().unwrap().is_ok());
+        });
+        assert!(fs::symlink_metadata(path).is_err());
+    }
+}
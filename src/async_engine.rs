@@ -0,0 +1,183 @@
+use std::sync::{atomic::AtomicBool, Arc};
+
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::{self, JoinHandle},
+};
+
+use crate::{
+    engine::{EngineCore, Outcome},
+    event::Event,
+    ledger::Ledger,
+    processor::EventProcessor,
+    store::AccountStore,
+    worker::Job,
+    QUEUE_CAPACITY, WORKERS_COUNT,
+};
+
+/// The engine for tokio services. Workers run as tasks sharded by client like the threads of
+/// `Engine`, so a client's events are still applied one at a time in submission order, but a
+/// full queue makes the submitter wait instead of dropping the event.
+pub struct AsyncEngine {
+    pub core: EngineCore,
+    queues: Vec<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl AsyncEngine {
+    /// Spawns the workers on the current tokio runtime.
+    pub fn start(ledger: Ledger, chart: Arc<dyn AccountStore>) -> Self {
+        let core = EngineCore {
+            ledger,
+            chart,
+            shutdown: Arc::new(AtomicBool::new(false)),
+        };
+        let mut queues = Vec::with_capacity(WORKERS_COUNT);
+        let mut workers = Vec::with_capacity(WORKERS_COUNT);
+        for _ in 0..WORKERS_COUNT {
+            let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+            queues.push(sender);
+            workers.push(tokio::spawn(work(
+                Arc::new(EventProcessor::new(core.clone())),
+                receiver,
+            )));
+        }
+        AsyncEngine {
+            core,
+            queues,
+            workers,
+        }
+    }
+
+    /// Submits the event and waits until it's been applied or rejected.
+    pub async fn submit(&self, event: Event) -> Outcome {
+        match self.enqueue(event).await {
+            Ok(outcome) => outcome
+                .await
+                .unwrap_or_else(|_| Outcome::rejected("The engine has stopped")),
+            Err(message) => Outcome::rejected(&message),
+        }
+    }
+
+    /// The lower-level `submit`: records the event in the ledger and queues it for its worker,
+    /// waiting while the queue is
+    /// full. The returned receiver resolves to the outcome; it may be dropped, and events queued
+    /// one after another are applied in that order either way. Repeated transactions are queued
+    /// as well, so their outcome reports the account after every event queued before them. The
+    /// ledger is written on the blocking pool, as its store may be on disk.
+    pub async fn enqueue(&self, event: Event) -> Result<oneshot::Receiver<Outcome>, String> {
+        let (reply, outcome) = oneshot::channel();
+        let ledger = self.core.ledger.clone();
+        let submitted = event.clone();
+        let recorded = task::spawn_blocking(move || ledger.add_event(submitted))
+            .await
+            .map_err(|e| format!("Can't record the event: {}", e))?;
+        let job = match recorded? {
+            None => Job::repeated(event),
+            Some(event) => Job::new(event),
+        };
+        let job = job.with_reply(reply);
+        let worker_idx = (job.event.client as usize) % self.queues.len();
+        if let Err(mpsc::error::SendError(job)) = self.queues[worker_idx].send(job).await {
+            if !job.repeated {
+                let ledger = self.core.ledger.clone();
+                let _ = task::spawn_blocking(move || ledger.discard(&job.event)).await;
+            }
+            return Err(String::from("The engine has stopped"));
+        }
+        Ok(outcome)
+    }
+
+    /// Closes the queues and waits for the workers to apply everything queued so far.
+    pub async fn shutdown(self) -> EngineCore {
+        drop(self.queues);
+        for worker in self.workers {
+            if let Err(e) = worker.await {
+                eprintln!("Worker failed: {}", e);
+            }
+        }
+        self.core
+    }
+}
+
+/// Applies the jobs on the blocking pool, one at a time so the client's order holds, as the
+/// processor takes the account and ledger stores' locks and may go to disk.
+async fn work(processor: Arc<EventProcessor>, mut queue: mpsc::Receiver<Job>) {
+    while let Some(job) = queue.recv().await {
+        let processor = processor.clone();
+        // A job whose worker panicked drops its reply, which reads as a stopped engine.
+        let _ = task::spawn_blocking(move || job.run(&processor)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryChart;
+    use rust_decimal::dec;
+
+    fn engine() -> AsyncEngine {
+        AsyncEngine::start(Ledger::default(), Arc::new(MemoryChart::default()))
+    }
+
+    #[tokio::test]
+    async fn test_submit_outcomes() {
+        let engine = engine();
+
+        let outcome = engine.submit(Event::deposit(1, 1, 10.0)).await;
+        assert_eq!(outcome.result, Ok(()));
+        assert_eq!(outcome.account.unwrap().available(), dec!(10));
+        assert_eq!(
+            engine.submit(Event::withdrawal(1, 2, 15.0)).await.result,
+            Err(String::from("Insufficient funds for transaction 2"))
+        );
+        let outcome = engine.submit(Event::deposit(1, 1, 10.0)).await;
+        assert_eq!(outcome.result, Ok(()));
+        assert_eq!(outcome.account.unwrap().available(), dec!(10));
+        assert_eq!(
+            engine.submit(Event::withdrawal(1, 2, 15.0)).await.result,
+            Err(String::from("Insufficient funds for transaction 2")),
+            "a repeat reports what became of the original"
+        );
+        assert_eq!(
+            engine.submit(Event::dispute(1, 9)).await.result,
+            Err(String::from(
+                "Can't find a transaction with ID 9 for client 1"
+            ))
+        );
+
+        let core = engine.shutdown().await;
+        assert_eq!(core.chart.account(1).unwrap().available(), dec!(10));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_keep_client_order() {
+        let engine = engine();
+
+        let mut outcomes = Vec::new();
+        for tx in 0..500 {
+            let client = (tx % 7) as u16;
+            outcomes.push(
+                engine
+                    .enqueue(Event::deposit(client, tx, 1.0))
+                    .await
+                    .unwrap(),
+            );
+            outcomes.push(
+                engine
+                    .enqueue(Event::withdrawal(client, tx + 1000, 1.0))
+                    .await
+                    .unwrap(),
+            );
+        }
+        for outcome in outcomes {
+            // Every withdrawal follows the deposit that funds it.
+            assert_eq!(outcome.await.unwrap().result, Ok(()));
+        }
+
+        let core = engine.shutdown().await;
+        for account in core.chart.accounts() {
+            assert_eq!(account.available(), dec!(0));
+        }
+    }
+}
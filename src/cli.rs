@@ -0,0 +1,501 @@
+use std::{cell::Cell, collections::HashMap, str::FromStr};
+
+use crate::{ledger::TxScope, replay::Until};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+/// Every option some command takes.
+const OPTIONS: [&str; 21] = [
+    "--tx",
+    "--client",
+    "--state",
+    "--at",
+    "--addr",
+    "--tcp",
+    "--admin",
+    "--log",
+    "--line",
+    "--summary",
+    "--dispute-report",
+    "--open-disputes",
+    "--mismatches",
+    "--export-sqlite",
+    "--tx-scope",
+    "--runtime",
+    "--dispute-window",
+    "--archive",
+    "--store",
+    "--dispute-profile",
+    "--dispute-cycles",
+];
+
+/// What runs the workers when an input file is loaded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Runtime {
+    Threads,
+    Tokio,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Process {
+        input: String,
+        summary: Option<String>,
+        dispute_report: Option<String>,
+        open_disputes: Option<String>,
+        mismatches: Option<String>,
+        export_sqlite: Option<String>,
+    },
+    History {
+        tx: u32,
+        client: Option<u16>,
+        state: String,
+    },
+    Statement {
+        client: u16,
+        state: String,
+        at: Option<u32>,
+    },
+    Verify {
+        state: String,
+    },
+    Serve {
+        addr: String,
+    },
+    Listen {
+        tcp: String,
+        admin: Option<String>,
+    },
+    Replay {
+        log: String,
+        until: Until,
+    },
+}
+
+impl Command {
+    /// Whether the command reports from the per-client index, the counters, the carried balances
+    /// or the event sequence, which only cover the events loaded by the run itself.
+    pub fn reads_run_state(&self) -> bool {
+        match self {
+            Command::Statement { .. } | Command::Verify { .. } => true,
+            Command::Process {
+                summary,
+                open_disputes,
+                export_sqlite,
+                ..
+            } => summary.is_some() || open_disputes.is_some() || export_sqlite.is_some(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub dispute_profile: Option<String>,
+    pub dispute_cycles: Option<usize>,
+    pub tx_scope: TxScope,
+    pub dispute_window: Option<u64>,
+    pub archive: Option<String>,
+    pub store: Option<String>,
+    pub runtime: Runtime,
+}
+
+impl Cli {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let (name, rest) = args
+            .split_first()
+            .ok_or_else(|| String::from("No arguments provided"))?;
+        let flags = Flags::parse(rest)?;
+
+        let command = match name.as_str() {
+            "history" => Command::History {
+                tx: flags.number("--tx")?,
+                client: flags.optional_number("--client")?,
+                state: flags.required("--state")?.to_string(),
+            },
+            "statement" => Command::Statement {
+                client: flags.number("--client")?,
+                state: flags.required("--state")?.to_string(),
+                at: flags.optional_number("--at")?,
+            },
+            "verify" => Command::Verify {
+                state: flags.required("--state")?.to_string(),
+            },
+            "serve" => Command::Serve {
+                addr: flags
+                    .optional("--addr")
+                    .unwrap_or_else(|| String::from(DEFAULT_ADDR)),
+            },
+            "listen" => Command::Listen {
+                tcp: flags.required("--tcp")?.to_string(),
+                admin: flags.optional("--admin"),
+            },
+            "replay" => Command::Replay {
+                log: flags.required("--log")?.to_string(),
+                until: match (
+                    flags.optional_number("--tx")?,
+                    flags.optional_number("--line")?,
+                ) {
+                    (Some(tx), None) => Until::Tx(tx),
+                    (None, Some(line)) => Until::Line(line),
+                    _ => return Err(String::from("replay needs either --tx or --line")),
+                },
+            },
+            input => Command::Process {
+                input: input.to_string(),
+                summary: flags.optional("--summary"),
+                dispute_report: flags.optional("--dispute-report"),
+                open_disputes: flags.optional("--open-disputes"),
+                mismatches: flags.optional("--mismatches"),
+                export_sqlite: flags.optional("--export-sqlite"),
+            },
+        };
+        let tx_scope = match flags.optional("--tx-scope").as_deref() {
+            None | Some("global") => TxScope::Global,
+            Some("client") => TxScope::Client,
+            Some(other) => return Err(format!("Invalid value {} for --tx-scope", other)),
+        };
+        let runtime = match flags.optional("--runtime").as_deref() {
+            None | Some("threads") => Runtime::Threads,
+            Some("tokio") => Runtime::Tokio,
+            Some(other) => return Err(format!("Invalid value {} for --runtime", other)),
+        };
+        let dispute_window = flags.optional_number("--dispute-window")?;
+        if dispute_window == Some(0) {
+            return Err(String::from("Invalid value 0 for --dispute-window"));
+        }
+        let archive = flags.optional("--archive");
+        if archive.is_some() && dispute_window.is_none() {
+            return Err(String::from("--archive requires --dispute-window"));
+        }
+
+        let store = flags.optional("--store");
+        if store.is_some() && matches!(command, Command::Replay { .. }) {
+            return Err(String::from("replay can't write to --store"));
+        }
+
+        let cli = Cli {
+            command,
+            dispute_profile: flags.optional("--dispute-profile"),
+            dispute_cycles: flags.optional_number("--dispute-cycles")?,
+            tx_scope,
+            dispute_window,
+            archive,
+            store,
+            runtime,
+        };
+        match cli.command {
+            Command::Process { .. } => flags.check_unused("processing a file")?,
+            _ => flags.check_unused(name)?,
+        }
+        Ok(cli)
+    }
+}
+
+struct Flags<'a> {
+    /// Every option given, its value and whether the command read it.
+    values: HashMap<&'a str, (&'a str, Cell<bool>)>,
+}
+
+impl<'a> Flags<'a> {
+    fn parse(args: &'a [String]) -> Result<Self, String> {
+        let mut values = HashMap::new();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            if !flag.starts_with("--") {
+                return Err(format!("Unexpected argument {}", flag));
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for {}", flag))?;
+            if !OPTIONS.contains(&flag.as_str()) {
+                return Err(format!("Unknown option {}", flag));
+            }
+            values.insert(flag.as_str(), (value.as_str(), Cell::new(false)));
+        }
+        Ok(Flags { values })
+    }
+
+    fn get(&self, name: &str) -> Option<&'a str> {
+        self.values.get(name).map(|(value, read)| {
+            read.set(true);
+            *value
+        })
+    }
+
+    fn required(&self, name: &str) -> Result<&'a str, String> {
+        self.get(name)
+            .ok_or_else(|| format!("Missing required option {}", name))
+    }
+
+    fn optional(&self, name: &str) -> Option<String> {
+        self.get(name).map(|value| value.to_string())
+    }
+
+    fn number<T: FromStr>(&self, name: &str) -> Result<T, String> {
+        let value = self.required(name)?;
+        value
+            .parse()
+            .map_err(|_| format!("Invalid value {} for {}", value, name))
+    }
+
+    fn optional_number<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.values.get(name) {
+            Some(_) => self.number(name).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Rejects the options the command never read, as they'd be silently ignored.
+    fn check_unused(&self, command: &str) -> Result<(), String> {
+        let mut unused: Vec<&str> = self
+            .values
+            .iter()
+            .filter(|(_, (_, read))| !read.get())
+            .map(|(flag, _)| *flag)
+            .collect();
+        unused.sort();
+        match unused.first() {
+            Some(flag) => Err(format!("{} doesn't apply to {}", flag, command)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn parse(line: &str) -> Result<Command, String> {
+        Cli::parse(&args(line)).map(|cli| cli.command)
+    }
+
+    #[test]
+    fn test_parse_process() {
+        assert_eq!(
+            parse("transactions.csv"),
+            Ok(Command::Process {
+                input: String::from("transactions.csv"),
+                summary: None,
+                dispute_report: None,
+                open_disputes: None,
+                mismatches: None,
+                export_sqlite: None,
+            })
+        );
+        assert_eq!(
+            parse(concat!(
+                "transactions.csv --summary - --dispute-report disputes.csv ",
+                "--open-disputes open.csv --export-sqlite out.db"
+            )),
+            Ok(Command::Process {
+                input: String::from("transactions.csv"),
+                summary: Some(String::from("-")),
+                dispute_report: Some(String::from("disputes.csv")),
+                open_disputes: Some(String::from("open.csv")),
+                mismatches: None,
+                export_sqlite: Some(String::from("out.db")),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_history() {
+        assert_eq!(
+            parse("history --tx 12345 --state transactions.csv"),
+            Ok(Command::History {
+                tx: 12345,
+                client: None,
+                state: String::from("transactions.csv")
+            })
+        );
+        assert_eq!(
+            parse("history --tx 12345 --client 3 --state transactions.csv"),
+            Ok(Command::History {
+                tx: 12345,
+                client: Some(3),
+                state: String::from("transactions.csv")
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_statement() {
+        assert_eq!(
+            parse("statement --state transactions.csv --client 7"),
+            Ok(Command::Statement {
+                client: 7,
+                state: String::from("transactions.csv"),
+                at: None,
+            })
+        );
+        assert_eq!(
+            parse("statement --state transactions.csv --client 7 --at 1000"),
+            Ok(Command::Statement {
+                client: 7,
+                state: String::from("transactions.csv"),
+                at: Some(1000),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_verify() {
+        assert_eq!(
+            parse("verify --state transactions.csv"),
+            Ok(Command::Verify {
+                state: String::from("transactions.csv")
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_serve() {
+        assert_eq!(
+            parse("serve"),
+            Ok(Command::Serve {
+                addr: String::from("127.0.0.1:8080")
+            })
+        );
+        assert_eq!(
+            parse("serve --addr 0.0.0.0:9000"),
+            Ok(Command::Serve {
+                addr: String::from("0.0.0.0:9000")
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_listen() {
+        assert_eq!(
+            parse("listen --tcp 0.0.0.0:7000"),
+            Ok(Command::Listen {
+                tcp: String::from("0.0.0.0:7000"),
+                admin: None,
+            })
+        );
+        assert_eq!(
+            parse("listen --tcp 0.0.0.0:7000 --admin /tmp/engine.sock"),
+            Ok(Command::Listen {
+                tcp: String::from("0.0.0.0:7000"),
+                admin: Some(String::from("/tmp/engine.sock")),
+            })
+        );
+        assert!(parse("listen").is_err());
+    }
+
+    #[test]
+    fn test_parse_replay() {
+        assert_eq!(
+            parse("replay --log a.csv --tx 1000"),
+            Ok(Command::Replay {
+                log: String::from("a.csv"),
+                until: Until::Tx(1000),
+            })
+        );
+        assert_eq!(
+            parse("replay --log a.csv --line 250"),
+            Ok(Command::Replay {
+                log: String::from("a.csv"),
+                until: Until::Line(250),
+            })
+        );
+        assert!(parse("replay --log a.csv").is_err());
+        assert!(parse("replay --log a.csv --tx 1 --line 2").is_err());
+        assert!(parse("replay --log a.csv --tx 1 --store engine.db").is_err());
+    }
+
+    #[test]
+    fn test_parse_dispute_options() {
+        let cli = Cli::parse(&args("transactions.csv")).unwrap();
+        assert_eq!(cli.dispute_profile, None);
+        assert_eq!(cli.dispute_cycles, None);
+
+        let cli = Cli::parse(&args(
+            "verify --state a.csv --dispute-cycles 3 --dispute-profile ach.csv",
+        ))
+        .unwrap();
+        assert_eq!(cli.dispute_profile, Some(String::from("ach.csv")));
+        assert_eq!(cli.dispute_cycles, Some(3));
+    }
+
+    #[test]
+    fn test_parse_tx_scope() {
+        let scope = |line: &str| Cli::parse(&args(line)).map(|cli| cli.tx_scope);
+
+        assert_eq!(scope("a.csv"), Ok(TxScope::Global));
+        assert_eq!(scope("a.csv --tx-scope client"), Ok(TxScope::Client));
+        assert!(scope("a.csv --tx-scope account").is_err());
+    }
+
+    #[test]
+    fn test_parse_runtime() {
+        let runtime = |line: &str| Cli::parse(&args(line)).map(|cli| cli.runtime);
+
+        assert_eq!(runtime("a.csv"), Ok(Runtime::Threads));
+        assert_eq!(runtime("a.csv --runtime tokio"), Ok(Runtime::Tokio));
+        assert!(runtime("a.csv --runtime fibers").is_err());
+    }
+
+    #[test]
+    fn test_parse_retention() {
+        let cli = Cli::parse(&args("a.csv --dispute-window 1000 --archive old.csv")).unwrap();
+        assert_eq!(cli.dispute_window, Some(1000));
+        assert_eq!(cli.archive, Some(String::from("old.csv")));
+
+        assert!(Cli::parse(&args("a.csv --dispute-window 0")).is_err());
+        assert!(Cli::parse(&args("a.csv --archive old.csv")).is_err());
+    }
+
+    #[test]
+    fn test_parse_store() {
+        let store = |line: &str| Cli::parse(&args(line)).map(|cli| cli.store);
+
+        assert_eq!(store("a.csv"), Ok(None));
+        assert_eq!(
+            store("verify --state a.csv --store engine.db"),
+            Ok(Some(String::from("engine.db")))
+        );
+    }
+
+    #[test]
+    fn test_reads_run_state() {
+        let reads = |line: &str| parse(line).map(|command| command.reads_run_state());
+
+        assert_eq!(reads("verify --state a.csv"), Ok(true));
+        assert_eq!(reads("statement --client 1 --state a.csv"), Ok(true));
+        assert_eq!(reads("a.csv --summary -"), Ok(true));
+        assert_eq!(reads("a.csv --open-disputes -"), Ok(true));
+        assert_eq!(reads("a.csv --export-sqlite a.db"), Ok(true));
+        assert_eq!(reads("a.csv --dispute-report -"), Ok(false));
+        assert_eq!(reads("a.csv"), Ok(false));
+        assert_eq!(reads("history --tx 1 --state a.csv"), Ok(false));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Cli::parse(&[]).is_err());
+        assert!(parse("history --tx 1").is_err());
+        assert!(parse("history --tx abc --state a.csv").is_err());
+        assert!(parse("history --tx 1 --state").is_err());
+        assert!(parse("statement --client 70000 --state a.csv").is_err());
+        assert!(parse("a.csv --dispute-cycles -1").is_err());
+        assert_eq!(
+            parse("a.csv --summry - --open-dispute x.csv"),
+            Err(String::from("Unknown option --summry"))
+        );
+        assert_eq!(
+            parse("a.csv --addr 127.0.0.1:9000"),
+            Err(String::from("--addr doesn't apply to processing a file"))
+        );
+        assert_eq!(
+            parse("verify --state a.csv --summary -"),
+            Err(String::from("--summary doesn't apply to verify"))
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    ledger::{DisputeEvent, Ledger},
+    lifecycle::Effect,
+};
+
+const UNSPECIFIED: &str = "unspecified";
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct ReasonLine {
+    pub reason: String,
+    pub transactions: usize,
+    pub disputed: Decimal,
+    pub resolved: Decimal,
+    pub charged_back: Decimal,
+    pub reversed: Decimal,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct OpenDispute {
+    pub client: u16,
+    pub tx: u32,
+    pub held: Decimal,
+    /// The position in the input of the event that opened the dispute.
+    pub opened_at: u64,
+    /// How many events the ledger was given since the dispute opened.
+    pub age: u64,
+    pub dispute_events: usize,
+}
+
+/// Every transaction with funds still held under dispute, oldest dispute first.
+pub fn open(ledger: &Ledger) -> Vec<OpenDispute> {
+    let mut disputes: Vec<OpenDispute> = ledger
+        .disputed()
+        .into_iter()
+        .filter(|history| history.disputed > Decimal::ZERO)
+        .map(|history| {
+            let mut held = Decimal::ZERO;
+            let mut opened = 0;
+            for (idx, record) in history.disputes.iter().enumerate() {
+                if held.is_zero() && record.effect == Effect::Hold {
+                    opened = idx;
+                }
+                match record.effect {
+                    Effect::Hold => held += record.amount,
+                    Effect::Resolve | Effect::Reject => held -= record.amount,
+                    Effect::Restore | Effect::None => {}
+                }
+            }
+
+            let opened_at = history.disputes[opened].sequence;
+            OpenDispute {
+                client: history.transaction.client(),
+                tx: history.id,
+                held: history.disputed,
+                opened_at,
+                age: ledger.sequence() - opened_at,
+                dispute_events: history.disputes.len() - opened,
+            }
+        })
+        .collect();
+
+    disputes.sort_by_key(|dispute| (dispute.opened_at, dispute.tx));
+    disputes
+}
+
+/// Totals the recorded dispute events by reason. Events without a reason count towards the
+/// latest reason given for the same transaction.
+pub fn by_reason(ledger: &Ledger) -> Vec<ReasonLine> {
+    let mut lines: BTreeMap<String, (ReasonLine, BTreeSet<u32>)> = BTreeMap::new();
+
+    for history in ledger.disputed() {
+        let mut reason = String::from(UNSPECIFIED);
+        for record in history.disputes {
+            if let Some(given) = record.details.reason {
+                reason = given;
+            }
+            let (line, transactions) = lines.entry(reason.clone()).or_insert_with(|| {
+                (
+                    ReasonLine {
+                        reason: reason.clone(),
+                        ..Default::default()
+                    },
+                    BTreeSet::new(),
+                )
+            });
+            transactions.insert(history.id);
+            match record.event {
+                DisputeEvent::Dispute => line.disputed += record.amount,
+                DisputeEvent::Resolve => line.resolved += record.amount,
+                DisputeEvent::Chargeback => line.charged_back += record.amount,
+                DisputeEvent::ChargebackReversal => line.reversed += record.amount,
+            }
+        }
+    }
+
+    lines
+        .into_values()
+        .map(|(line, transactions)| ReasonLine {
+            transactions: transactions.len(),
+            ..line
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::EngineCore, event::Event, processor::EventProcessor};
+    use rust_decimal::dec;
+
+    #[test]
+    fn test_open_disputes() {
+        let engine_core = EngineCore::default();
+        let processor = EventProcessor::new(engine_core.clone());
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::deposit(2, 2, 20.0),
+            Event::deposit(2, 3, 5.0),
+            Event::dispute(2, 2).with_amount(5.0),
+            Event::dispute(1, 1),
+            Event::resolve(1, 1),
+            Event::dispute(2, 2),
+            Event::dispute(2, 3),
+            Event::deposit(1, 4, 1.0),
+        ];
+        for event in events {
+            let event = engine_core.ledger.add_event(event).unwrap().unwrap();
+            let _ = processor.process(&event);
+        }
+
+        assert_eq!(
+            open(&engine_core.ledger),
+            vec![
+                OpenDispute {
+                    client: 2,
+                    tx: 2,
+                    held: dec!(20),
+                    opened_at: 4,
+                    age: 5,
+                    dispute_events: 2,
+                },
+                OpenDispute {
+                    client: 2,
+                    tx: 3,
+                    held: dec!(5),
+                    opened_at: 8,
+                    age: 1,
+                    dispute_events: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_by_reason() {
+        let ledger = Ledger::default();
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::deposit(1, 2, 20.0),
+            Event::deposit(2, 3, 5.0),
+            Event::deposit(2, 4, 8.0),
+            Event::dispute(1, 1).with_reason("fraud"),
+            Event::chargeback(1, 1),
+            Event::dispute(1, 2)
+                .with_amount(10.0)
+                .with_reason("duplicate"),
+            Event::resolve(1, 2),
+            Event::dispute(2, 3).with_reason("fraud"),
+            Event::dispute(2, 4),
+        ];
+        for event in events {
+            ledger.record(event).unwrap();
+        }
+
+        assert_eq!(
+            by_reason(&ledger),
+            vec![
+                ReasonLine {
+                    reason: String::from("duplicate"),
+                    transactions: 1,
+                    disputed: dec!(10),
+                    resolved: dec!(10),
+                    ..Default::default()
+                },
+                ReasonLine {
+                    reason: String::from("fraud"),
+                    transactions: 2,
+                    disputed: dec!(35),
+                    charged_back: dec!(30),
+                    ..Default::default()
+                },
+                ReasonLine {
+                    reason: String::from("unspecified"),
+                    transactions: 1,
+                    disputed: dec!(8),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
     thread::{self, JoinHandle},
@@ -8,31 +8,89 @@ use std::{
 };
 
 use crossbeam::queue::ArrayQueue;
-use dashmap::DashMap;
+use tokio::sync::oneshot;
 
-use crate::{account::Account, event::Event, ledger::Ledger, worker::Worker, WORKERS_COUNT};
+use crate::{
+    account::Account,
+    event::Event,
+    ledger::{Balance, Ledger},
+    store::{AccountStore, MemoryChart},
+    worker::{Job, Worker},
+    WORKERS_COUNT,
+};
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct EngineCore {
     pub ledger: Ledger,
-    pub chart: Arc<DashMap<u16, Account>>,
+    pub chart: Arc<dyn AccountStore>,
     pub shutdown: Arc<AtomicBool>,
 }
 
+impl Default for EngineCore {
+    fn default() -> Self {
+        EngineCore {
+            ledger: Ledger::default(),
+            chart: Arc::new(MemoryChart::default()),
+            shutdown: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
 pub struct Engine {
     pub core: EngineCore,
-    pub queues: Vec<Arc<ArrayQueue<Event>>>,
+    pub queues: Vec<Arc<ArrayQueue<Job>>>,
+    pub processed: Vec<Arc<AtomicU64>>,
+}
+
+/// What became of a submitted event once its worker got to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    /// `Ok` once applied to the account, or the reason it was rejected.
+    pub result: Result<(), String>,
+    /// The client's account right after the event, if it has one.
+    pub account: Option<Account>,
+}
+
+impl Outcome {
+    /// An event rejected before it reached a worker.
+    pub fn rejected(reason: &str) -> Self {
+        Outcome {
+            result: Err(reason.to_string()),
+            account: None,
+        }
+    }
+}
+
+/// Resolves to the outcome of an event submitted with `Engine::submit_tracked`.
+pub struct Completion(oneshot::Receiver<Outcome>);
+
+impl Completion {
+    /// Blocks until the event has been applied or rejected. Not for use on a tokio runtime.
+    pub fn wait(self) -> Outcome {
+        self.0
+            .blocking_recv()
+            .unwrap_or_else(|_| Outcome::rejected("The engine has stopped"))
+    }
+}
+
+/// The load of one worker at a point in time.
+#[derive(Debug, PartialEq)]
+pub struct WorkerStatus {
+    pub worker: usize,
+    pub queued: usize,
+    pub processed: u64,
 }
 
 impl Engine {
-    pub fn new() -> Self {
+    pub fn new(ledger: Ledger, chart: Arc<dyn AccountStore>) -> Self {
         Engine {
             core: EngineCore {
-                ledger: Ledger::default(),
-                chart: Arc::new(DashMap::new()),
+                ledger,
+                chart,
                 shutdown: Arc::new(AtomicBool::new(false)),
             },
             queues: Vec::with_capacity(WORKERS_COUNT),
+            processed: Vec::with_capacity(WORKERS_COUNT),
         }
     }
 
@@ -41,18 +99,36 @@ impl Engine {
         for _ in 0..WORKERS_COUNT {
             let worker = Worker::new(self.core.clone());
             self.queues.push(worker.queue.clone());
+            self.processed.push(worker.processed.clone());
             workers.push(worker);
         }
         workers.into_iter().map(|worker| worker.start()).collect()
     }
 
     pub fn submit_event(&self, event: Event) -> Result<(), String> {
-        match self.core.ledger.add_event(event.clone()) {
-            Ok(_) => {
-                let worker_idx = (event.client as usize) % self.queues.len();
-                self.queues[worker_idx]
-                    .push(event)
-                    .map_err(|_| String::from("Queue is full"))
+        match self.core.ledger.add_event(event) {
+            Ok(None) => Ok(()),
+            Ok(Some(event)) => self.push(Job::new(event)),
+            Err(message) => {
+                eprintln!("{}", message);
+                Err(message.to_string())
+            }
+        }
+    }
+
+    /// Submits the event like `submit_event`, but waits for room in its client's queue instead of
+    /// giving up when the queue is full, so no event of a loaded file is lost.
+    pub fn submit_waiting(&self, event: Event) -> Result<(), String> {
+        match self.core.ledger.add_event(event) {
+            Ok(None) => Ok(()),
+            Ok(Some(event)) => {
+                let queue = &self.queues[(event.client as usize) % self.queues.len()];
+                let mut job = Job::new(event);
+                while let Err(full) = queue.push(job) {
+                    job = full;
+                    thread::yield_now();
+                }
+                Ok(())
             }
             Err(message) => {
                 eprintln!("{}", message);
@@ -61,6 +137,53 @@ impl Engine {
         }
     }
 
+    /// Submits the event like `submit_event`, returning a handle to wait for its outcome. A
+    /// repeated transaction still goes through its client's queue, so its outcome is that of the
+    /// original and reports the account after every event submitted before it.
+    pub fn submit_tracked(&self, event: Event) -> Result<Completion, String> {
+        let (reply, outcome) = oneshot::channel();
+        let job = match self.core.ledger.add_event(event.clone()) {
+            Ok(None) => Job::repeated(event),
+            Ok(Some(event)) => Job::new(event),
+            Err(message) => {
+                eprintln!("{}", message);
+                return Err(message.to_string());
+            }
+        };
+        self.push(job.with_reply(reply))?;
+        Ok(Completion(outcome))
+    }
+
+    /// An event that can't be queued is forgotten by the ledger, so a retry isn't taken for a
+    /// repeat of it.
+    fn push(&self, job: Job) -> Result<(), String> {
+        let worker_idx = (job.event.client as usize) % self.queues.len();
+        self.queues[worker_idx].push(job).map_err(|job| {
+            if !job.repeated {
+                self.core.ledger.discard(&job.event);
+            }
+            String::from("Queue is full")
+        })
+    }
+
+    pub fn status(&self) -> Vec<WorkerStatus> {
+        self.queues
+            .iter()
+            .zip(&self.processed)
+            .enumerate()
+            .map(|(worker, (queue, processed))| WorkerStatus {
+                worker,
+                queued: queue.len(),
+                processed: processed.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// The client's account fields right after transaction `tx`, once a worker has applied it.
+    pub fn balance_at(&self, client: u16, tx: u32) -> Result<Balance, String> {
+        self.core.ledger.balance_at(client, tx)
+    }
+
     pub fn shutdown(&self) {
         self.core.shutdown.store(true, Ordering::Relaxed);
         self.wait_for_workers();
@@ -72,3 +195,45 @@ impl Engine {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_after_full_queue() {
+        let mut engine = Engine::new(Ledger::default(), Arc::new(MemoryChart::default()));
+        // A single queue that no worker takes events off.
+        engine.queues.push(Arc::new(ArrayQueue::new(1)));
+        engine.processed.push(Arc::new(AtomicU64::new(0)));
+
+        assert_eq!(engine.submit_event(Event::deposit(1, 1, 10.0)), Ok(()));
+        assert_eq!(
+            engine.submit_tracked(Event::deposit(1, 2, 5.0)).err(),
+            Some(String::from("Queue is full"))
+        );
+        assert_eq!(engine.core.ledger.status(2, 1), None);
+
+        engine.queues[0].pop();
+        assert!(engine.submit_tracked(Event::deposit(1, 2, 5.0)).is_ok());
+        assert!(!engine.queues[0].pop().unwrap().repeated);
+    }
+
+    #[test]
+    fn test_wait_for_room_in_full_queue() {
+        let mut engine = Engine::new(Ledger::default(), Arc::new(MemoryChart::default()));
+        engine.queues.push(Arc::new(ArrayQueue::new(1)));
+        engine.processed.push(Arc::new(AtomicU64::new(0)));
+        assert_eq!(engine.submit_waiting(Event::deposit(1, 1, 10.0)), Ok(()));
+
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| engine.submit_waiting(Event::deposit(1, 2, 5.0)));
+            thread::sleep(Duration::from_millis(50));
+            assert!(!waiting.is_finished());
+
+            assert_eq!(engine.queues[0].pop().unwrap().event.tx, 1);
+            assert_eq!(waiting.join().unwrap(), Ok(()));
+        });
+        assert_eq!(engine.queues[0].pop().unwrap().event.tx, 2);
+    }
+}
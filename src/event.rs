@@ -8,6 +8,12 @@ pub enum EventType {
     Dispute,
     Resolve,
     Chargeback,
+    #[serde(rename = "chargeback_reversal")]
+    ChargebackReversal,
+    Close,
+    /// An operator locking the account; only the admin socket submits it, never the input.
+    #[serde(skip)]
+    Lock,
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -17,4 +23,14 @@ pub struct Event {
     pub client: u16,
     pub tx: u32,
     pub amount: Option<f64>,
+    /// Dispute metadata, optional columns that only dispute events are expected to fill in.
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub case_id: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// The position of the event in the ledger's input, set once the ledger records it.
+    #[serde(skip)]
+    pub sequence: u64,
 }
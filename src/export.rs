@@ -0,0 +1,218 @@
+use rusqlite::{params, Connection};
+
+use crate::{ledger::Ledger, store::AccountStore};
+
+/// Decimals are stored as text so no precision is lost; SQLite booleans are 0 or 1. Dispute events
+/// get a key of their own, as a store kept over several runs repeats input positions.
+const SCHEMA: &str = "
+    DROP TABLE IF EXISTS accounts;
+    DROP TABLE IF EXISTS transactions;
+    DROP TABLE IF EXISTS dispute_events;
+    CREATE TABLE accounts (
+        client INTEGER PRIMARY KEY,
+        available TEXT NOT NULL,
+        held TEXT NOT NULL,
+        total TEXT NOT NULL,
+        locked BOOLEAN NOT NULL,
+        closed BOOLEAN NOT NULL
+    );
+    CREATE TABLE transactions (
+        tx INTEGER NOT NULL,
+        client INTEGER NOT NULL,
+        type TEXT NOT NULL,
+        amount TEXT NOT NULL,
+        status TEXT NOT NULL,
+        disputed TEXT NOT NULL,
+        PRIMARY KEY (tx, client)
+    );
+    CREATE TABLE dispute_events (
+        id INTEGER PRIMARY KEY,
+        sequence INTEGER NOT NULL,
+        tx INTEGER NOT NULL,
+        client INTEGER NOT NULL,
+        event TEXT NOT NULL,
+        amount TEXT NOT NULL,
+        state TEXT NOT NULL,
+        effect TEXT NOT NULL,
+        reason TEXT,
+        case_id TEXT,
+        notes TEXT
+    );
+";
+
+/// Writes the accounts, transactions and dispute events to a SQLite database, replacing the
+/// tables of an earlier export.
+pub fn sqlite(path: &str, ledger: &Ledger, chart: &dyn AccountStore) -> Result<(), String> {
+    let mut conn =
+        Connection::open(path).map_err(|e| format!("Can't open the database {}: {}", path, e))?;
+    write(&mut conn, ledger, chart).map_err(|e| format!("Can't export to {}: {}", path, e))
+}
+
+fn write(conn: &mut Connection, ledger: &Ledger, chart: &dyn AccountStore) -> rusqlite::Result<()> {
+    let db = conn.transaction()?;
+    db.execute_batch(SCHEMA)?;
+
+    {
+        let mut insert = db.prepare(
+            "INSERT INTO accounts (client, available, held, total, locked, closed)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for account in chart.accounts() {
+            insert.execute(params![
+                account.client(),
+                account.available().to_string(),
+                account.held().to_string(),
+                account.total().to_string(),
+                account.locked(),
+                account.closed(),
+            ])?;
+        }
+
+        let mut insert_transaction = db.prepare(
+            "INSERT INTO transactions (tx, client, type, amount, status, disputed)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        let mut insert_dispute = db.prepare(
+            "INSERT INTO dispute_events
+            (sequence, tx, client, event, amount, state, effect, reason, case_id, notes)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?;
+        for history in ledger.transactions() {
+            let client = history.transaction.client();
+            insert_transaction.execute(params![
+                history.id,
+                client,
+                history.transaction.kind(),
+                history.transaction.amount().to_string(),
+                history.status.to_string(),
+                history.disputed.to_string(),
+            ])?;
+            for record in history.disputes {
+                insert_dispute.execute(params![
+                    record.sequence as i64,
+                    history.id,
+                    client,
+                    record.event.to_string(),
+                    record.amount.to_string(),
+                    record.state,
+                    record.effect.to_string(),
+                    record.details.reason,
+                    record.details.case_id,
+                    record.details.notes,
+                ])?;
+            }
+        }
+    }
+
+    db.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::EngineCore, event::Event, processor::EventProcessor, store::MemoryLedger};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_export_tables() {
+        let engine_core = EngineCore::default();
+        let processor = EventProcessor::new(engine_core.clone());
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 10.5),
+            Event::deposit(2, 3, 7.0),
+            Event::dispute(2, 3).with_reason("fraud"),
+            Event::chargeback(2, 3),
+        ];
+        for event in events {
+            let event = engine_core.ledger.add_event(event).unwrap().unwrap();
+            let _ = processor.process(&event);
+        }
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        write(&mut conn, &engine_core.ledger, engine_core.chart.as_ref()).unwrap();
+        // Exporting again replaces the earlier tables.
+        write(&mut conn, &engine_core.ledger, engine_core.chart.as_ref()).unwrap();
+
+        let accounts: Vec<(u16, String, bool)> = conn
+            .prepare("SELECT client, available, locked FROM accounts ORDER BY client")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            accounts,
+            vec![
+                (1, String::from("19.5"), false),
+                (2, String::from("7"), true)
+            ]
+        );
+
+        let transactions: Vec<(u32, String, String)> = conn
+            .prepare("SELECT tx, type, status FROM transactions ORDER BY tx")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(
+            transactions[2],
+            (3, String::from("deposit"), String::from("charged back"))
+        );
+
+        let disputes: Vec<(String, String, Option<String>)> = conn
+            .prepare("SELECT event, effect, reason FROM dispute_events ORDER BY sequence")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            disputes,
+            vec![
+                (
+                    String::from("dispute"),
+                    String::from("hold"),
+                    Some(String::from("fraud"))
+                ),
+                (String::from("chargeback"), String::from("reject"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_disputes_of_several_runs() {
+        let store = Arc::new(MemoryLedger::default());
+        let chart = EngineCore::default().chart;
+        for events in [
+            vec![Event::deposit(1, 1, 10.0), Event::dispute(1, 1)],
+            vec![Event::deposit(1, 2, 5.0), Event::dispute(1, 2)],
+        ] {
+            let engine_core = EngineCore {
+                ledger: Ledger::default().with_store(store.clone()),
+                chart: chart.clone(),
+                ..Default::default()
+            };
+            let processor = EventProcessor::new(engine_core.clone());
+            for event in events {
+                let event = engine_core.ledger.add_event(event).unwrap().unwrap();
+                processor.process(&event).unwrap();
+            }
+        }
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        let ledger = Ledger::default().with_store(store);
+        write(&mut conn, &ledger, chart.as_ref()).unwrap();
+
+        let disputes: Vec<(i64, u32)> = conn
+            .prepare("SELECT sequence, tx FROM dispute_events ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(disputes, vec![(2, 1), (2, 2)]);
+    }
+}
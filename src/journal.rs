@@ -0,0 +1,125 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The books every client's money can sit in. `External` is money outside the engine, so it
+/// goes negative as funds are deposited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Book {
+    Available,
+    Held,
+    External,
+}
+
+impl Book {
+    pub const ALL: [Book; 3] = [Book::Available, Book::Held, Book::External];
+}
+
+impl fmt::Display for Book {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Book::Available => write!(f, "available"),
+            Book::Held => write!(f, "held"),
+            Book::External => write!(f, "external"),
+        }
+    }
+}
+
+/// Only the running trial balance of the postings is kept, so an account stays the same size
+/// however many operations it has seen.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Journal {
+    trial_balance: TrialBalance,
+}
+
+impl Journal {
+    pub fn from_trial_balance(trial_balance: TrialBalance) -> Self {
+        Journal { trial_balance }
+    }
+
+    /// Moves `amount` out of the `credit` book into the `debit` book.
+    pub fn post(&mut self, debit: Book, credit: Book, amount: Decimal) {
+        self.trial_balance.record(debit, credit, amount);
+    }
+
+    pub fn balance(&self, book: Book) -> Decimal {
+        self.trial_balance.balance(book)
+    }
+
+    pub fn trial_balance(&self) -> &TrialBalance {
+        &self.trial_balance
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrialBalance {
+    debits: [Decimal; 3],
+    credits: [Decimal; 3],
+}
+
+impl TrialBalance {
+    pub fn debits(&self, book: Book) -> Decimal {
+        self.debits[book as usize]
+    }
+
+    pub fn credits(&self, book: Book) -> Decimal {
+        self.credits[book as usize]
+    }
+
+    pub fn balance(&self, book: Book) -> Decimal {
+        self.debits(book) - self.credits(book)
+    }
+
+    /// Every posting debits and credits the same amount, so the books always net to zero.
+    pub fn is_balanced(&self) -> bool {
+        self.debits.iter().sum::<Decimal>() == self.credits.iter().sum::<Decimal>()
+    }
+
+    pub fn merge(&mut self, other: &TrialBalance) {
+        for book in Book::ALL {
+            self.debits[book as usize] += other.debits(book);
+            self.credits[book as usize] += other.credits(book);
+        }
+    }
+
+    fn record(&mut self, debit: Book, credit: Book, amount: Decimal) {
+        self.debits[debit as usize] += amount;
+        self.credits[credit as usize] += amount;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::dec;
+
+    #[test]
+    fn test_postings_conserve_money() {
+        let mut journal = Journal::default();
+        journal.post(Book::Available, Book::External, dec!(30));
+        journal.post(Book::Held, Book::Available, dec!(10));
+        journal.post(Book::External, Book::Held, dec!(10));
+
+        assert_eq!(journal.balance(Book::Available), dec!(20));
+        assert_eq!(journal.balance(Book::Held), dec!(0));
+        assert_eq!(journal.balance(Book::External), dec!(-20));
+        assert!(journal.trial_balance().is_balanced());
+    }
+
+    #[test]
+    fn test_merge_trial_balances() {
+        let mut first = Journal::default();
+        first.post(Book::Available, Book::External, dec!(30));
+        let mut second = Journal::default();
+        second.post(Book::Held, Book::External, dec!(5));
+
+        let mut total = TrialBalance::default();
+        total.merge(first.trial_balance());
+        total.merge(second.trial_balance());
+
+        assert_eq!(total.debits(Book::Available), dec!(30));
+        assert_eq!(total.debits(Book::Held), dec!(5));
+        assert_eq!(total.credits(Book::External), dec!(35));
+        assert!(total.is_balanced());
+    }
+}
@@ -1,102 +1,694 @@
-use crate::event::{Event, EventType};
+use crate::{
+    event::{Event, EventType},
+    lifecycle::{AccountChange, DisputeProfile, Effect, Transition, UNDISPUTED},
+    store::{LedgerStore, MemoryLedger},
+};
+use csv::Writer;
 use dashmap::DashMap;
 use rust_decimal::{dec, prelude::FromPrimitive, Decimal};
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    fmt,
+    fs::File,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum Transaction {
     Deposit { amount: Decimal, client: u16 },
     Withdrawal { amount: Decimal, client: u16 },
+    Payout { amount: Decimal, client: u16 },
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl Transaction {
+    pub fn client(&self) -> u16 {
+        match self {
+            Transaction::Deposit { client, .. }
+            | Transaction::Withdrawal { client, .. }
+            | Transaction::Payout { client, .. } => *client,
+        }
+    }
+
+    pub fn amount(&self) -> Decimal {
+        match self {
+            Transaction::Deposit { amount, .. }
+            | Transaction::Withdrawal { amount, .. }
+            | Transaction::Payout { amount, .. } => *amount,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Transaction::Deposit { .. } => "deposit",
+            Transaction::Withdrawal { .. } => "withdrawal",
+            Transaction::Payout { .. } => "payout",
+        }
+    }
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transaction::Deposit { amount, client } => {
+                write!(f, "deposit of {} for client {}", amount, client)
+            }
+            Transaction::Withdrawal { amount, client } => {
+                write!(f, "withdrawal of {} for client {}", amount, client)
+            }
+            Transaction::Payout { amount, client } => {
+                write!(f, "payout of {} for client {}", amount, client)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum TransactionStatus {
+    Accepted,
+    Applied,
+    Rejected(String),
+    Disputed,
+    Resolved,
+    ChargedBack,
+    ChargebackReversed,
+}
+
+impl fmt::Display for TransactionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionStatus::Accepted => write!(f, "accepted"),
+            TransactionStatus::Applied => write!(f, "applied"),
+            TransactionStatus::Rejected(reason) => write!(f, "rejected ({})", reason),
+            TransactionStatus::Disputed => write!(f, "disputed"),
+            TransactionStatus::Resolved => write!(f, "resolved"),
+            TransactionStatus::ChargedBack => write!(f, "charged back"),
+            TransactionStatus::ChargebackReversed => write!(f, "chargeback reversed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LedgerEntry {
+    pub transaction: Transaction,
+    pub status: TransactionStatus,
+    /// The part of the amount the processor currently holds under dispute.
+    pub disputed: Decimal,
+    /// The dispute profile state the processor has moved the transaction to.
+    pub state: String,
+    /// The position in the input of the latest event naming the transaction.
+    pub touched: u64,
+    /// Dispute events submitted for the transaction that the processor hasn't taken yet. The
+    /// transaction isn't evicted while any are queued.
+    #[serde(default)]
+    pub pending: u32,
+}
+
+impl LedgerEntry {
+    fn new(transaction: Transaction, status: TransactionStatus, touched: u64) -> Self {
+        LedgerEntry {
+            transaction,
+            status,
+            disputed: dec!(0),
+            state: String::from(UNDISPUTED),
+            touched,
+            pending: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DisputeEvent {
     Dispute,
     Chargeback,
     Resolve,
+    #[serde(rename = "chargeback_reversal")]
+    ChargebackReversal,
+}
+
+impl fmt::Display for DisputeEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisputeEvent::Dispute => write!(f, "dispute"),
+            DisputeEvent::Chargeback => write!(f, "chargeback"),
+            DisputeEvent::Resolve => write!(f, "resolve"),
+            DisputeEvent::ChargebackReversal => write!(f, "chargeback_reversal"),
+        }
+    }
+}
+
+impl TryFrom<&EventType> for DisputeEvent {
+    type Error = String;
+
+    fn try_from(event: &EventType) -> Result<Self, Self::Error> {
+        match event {
+            EventType::Dispute => Ok(DisputeEvent::Dispute),
+            EventType::Chargeback => Ok(DisputeEvent::Chargeback),
+            EventType::Resolve => Ok(DisputeEvent::Resolve),
+            EventType::ChargebackReversal => Ok(DisputeEvent::ChargebackReversal),
+            _ => Err(format!("Unknown dispute format: {:?}", event)),
+        }
+    }
+}
+
+/// What the disputes team recorded about a dispute event.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct DisputeDetails {
+    pub reason: Option<String>,
+    pub case_id: Option<String>,
+    pub notes: Option<String>,
+}
+
+impl From<&Event> for DisputeDetails {
+    fn from(event: &Event) -> Self {
+        DisputeDetails {
+            reason: event.reason.clone(),
+            case_id: event.case_id.clone(),
+            notes: event.notes.clone(),
+        }
+    }
+}
+
+impl fmt::Display for DisputeDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(reason) = &self.reason {
+            write!(f, ", reason {}", reason)?;
+        }
+        if let Some(case_id) = &self.case_id {
+            write!(f, ", case {}", case_id)?;
+        }
+        if let Some(notes) = &self.notes {
+            write!(f, ", notes: {}", notes)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DisputeRecord {
+    pub event: DisputeEvent,
+    pub amount: Decimal,
+    /// The state the transaction moved to.
+    pub state: String,
+    pub effect: Effect,
+    pub details: DisputeDetails,
+    /// The position of the event in the ledger's input.
+    pub sequence: u64,
+}
+
+impl fmt::Display for DisputeRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} -> {}{}",
+            self.event, self.amount, self.state, self.details
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionHistory {
+    pub id: u32,
+    pub transaction: Transaction,
+    pub status: TransactionStatus,
+    /// The part of the amount currently held under dispute.
+    pub disputed: Decimal,
+    pub disputes: Vec<DisputeRecord>,
+}
+
+/// An event applied to a client's account, in the order the processor applied it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub tx: u32,
+    pub event: EventType,
+    pub amount: Decimal,
+    /// The dispute transition taken, for dispute events.
+    pub transition: Option<Transition>,
+    /// The client's running balances right after the event.
+    pub after: Rollup,
+}
+
+/// A client's applied events by the order they were applied in, with the keys of every
+/// transaction's events so evicting one transaction leaves the others' alone.
+#[derive(Debug, Default)]
+struct ClientIndex {
+    entries: BTreeMap<u64, IndexEntry>,
+    transactions: HashMap<u32, Vec<u64>>,
+    next: u64,
+    /// The balances after the latest event, including those of evicted transactions.
+    latest: Rollup,
+}
+
+impl ClientIndex {
+    fn push(&mut self, entry: IndexEntry) {
+        if entry.event != EventType::Lock {
+            self.transactions
+                .entry(entry.tx)
+                .or_default()
+                .push(self.next);
+        }
+        self.latest = entry.after.clone();
+        self.entries.insert(self.next, entry);
+        self.next += 1;
+    }
+
+    /// The event that introduced the transaction.
+    fn first(&self, tx: u32) -> Option<&IndexEntry> {
+        let key = self.transactions.get(&tx)?.first()?;
+        self.entries.get(key)
+    }
+
+    /// Takes the transaction's events out, returning them in order. Operator locks name no
+    /// transaction and stay.
+    fn remove(&mut self, tx: u32) -> Vec<IndexEntry> {
+        self.transactions
+            .remove(&tx)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|key| self.entries.remove(&key))
+            .collect()
+    }
+}
+
+/// A client's balances and totals rolled up from applied events, without the account chart.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rollup {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub locked: bool,
+    /// The transactions whose latest dispute transition locked the account.
+    pub locked_by: BTreeSet<u32>,
+    /// Whether an operator locked the account, which no dispute event lifts.
+    pub operator_lock: bool,
+    pub closed: bool,
+    pub deposits: Decimal,
+    pub withdrawals: Decimal,
+    pub payouts: Decimal,
+    pub charged_back: Decimal,
+    pub chargeback_reversals: Decimal,
+}
+
+/// A client's account fields as they stood at some point.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Balance {
+    pub client: u16,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: bool,
+    pub closed: bool,
+}
+
+impl Balance {
+    fn new(client: u16, rollup: &Rollup) -> Self {
+        Balance {
+            client,
+            available: rollup.available,
+            held: rollup.held,
+            total: rollup.available + rollup.held,
+            locked: rollup.locked,
+            closed: rollup.closed,
+        }
+    }
+}
+
+impl Rollup {
+    /// `on_deposit` tells whether a dispute event names a deposit or a withdrawal. The account
+    /// stays locked while any transaction that locked it hasn't been unlocked again, or once an
+    /// operator locked it.
+    pub fn apply(&mut self, entry: &IndexEntry, on_deposit: bool) {
+        let amount = entry.amount;
+        match entry.event {
+            EventType::Deposit => {
+                self.available += amount;
+                self.deposits += amount;
+            }
+            EventType::Withdrawal => {
+                self.available -= amount;
+                self.withdrawals += amount;
+            }
+            EventType::Close => {
+                self.available -= amount;
+                self.payouts += amount;
+                self.closed = true;
+            }
+            EventType::Lock => {
+                self.operator_lock = true;
+                self.locked = true;
+            }
+            EventType::Dispute
+            | EventType::Resolve
+            | EventType::Chargeback
+            | EventType::ChargebackReversal => {
+                match entry.event {
+                    EventType::Chargeback => self.charged_back += amount,
+                    EventType::ChargebackReversal => self.chargeback_reversals += amount,
+                    _ => {}
+                }
+                let Some(transition) = &entry.transition else {
+                    return;
+                };
+                match (transition.effect, on_deposit) {
+                    (Effect::Hold, true) => {
+                        self.available -= amount;
+                        self.held += amount;
+                    }
+                    (Effect::Hold, false) => self.held += amount,
+                    (Effect::Resolve, true) => self.held -= amount,
+                    (Effect::Resolve, false) | (Effect::Reject, true) => {
+                        self.held -= amount;
+                        self.available += amount;
+                    }
+                    (Effect::Reject, false) => self.held -= amount,
+                    (Effect::Restore, true) => self.available -= amount,
+                    (Effect::Restore, false) => self.available += amount,
+                    (Effect::None, _) => {}
+                }
+                match transition.account {
+                    Some(AccountChange::Lock) => {
+                        self.locked_by.insert(entry.tx);
+                    }
+                    Some(AccountChange::Unlock) => {
+                        self.locked_by.remove(&entry.tx);
+                    }
+                    None => return,
+                }
+                self.locked = self.operator_lock || !self.locked_by.is_empty();
+            }
+        }
+    }
+}
+
+/// A transaction evicted from memory once its dispute window passed.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ArchivedTransaction {
+    pub tx: u32,
+    pub client: u16,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub amount: Decimal,
+    pub status: String,
+    pub dispute_events: usize,
+}
+
+/// Whether transaction IDs are unique across all clients or only within a client.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum TxScope {
+    #[default]
+    Global,
+    Client,
 }
 
-#[derive(Default, Debug, Clone)]
+/// A dispute event naming a transaction that belongs to another client.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ClientMismatch {
+    pub sequence: u64,
+    pub client: u16,
+    pub tx: u32,
+    pub owner: u16,
+    pub event: DisputeEvent,
+}
+
+#[derive(Debug, Clone)]
 pub struct Ledger {
-    transactions: Arc<DashMap<u32, Transaction>>,
-    disputes: Arc<DashMap<u32, Vec<DisputeEvent>>>,
+    store: Arc<dyn LedgerStore>,
+    index: Arc<DashMap<u16, ClientIndex>>,
+    mismatches: Arc<AtomicU64>,
+    /// Where every client mismatch is written as it's found, rather than kept in memory.
+    mismatch_report: Option<Arc<Mutex<Writer<File>>>>,
+    duplicates: Arc<AtomicU64>,
+    sequence: Arc<AtomicU64>,
+    profile: DisputeProfile,
+    tx_scope: TxScope,
+    /// How many events a settled transaction stays in memory after it was last named.
+    dispute_window: Option<u64>,
+    recent: Arc<Mutex<VecDeque<(u64, u64)>>>,
+    carried: Arc<DashMap<u16, Rollup>>,
+    evicted: Arc<AtomicU64>,
+    archive: Option<Arc<Mutex<Writer<File>>>>,
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Ledger {
+            store: Arc::new(MemoryLedger::default()),
+            index: Default::default(),
+            mismatches: Default::default(),
+            mismatch_report: None,
+            duplicates: Default::default(),
+            sequence: Default::default(),
+            profile: Default::default(),
+            tx_scope: Default::default(),
+            dispute_window: None,
+            recent: Default::default(),
+            carried: Default::default(),
+            evicted: Default::default(),
+            archive: None,
+        }
+    }
 }
 
 impl Ledger {
-    pub fn add_event(&self, event: Event) -> Result<(), String> {
+    pub fn new(profile: DisputeProfile) -> Self {
+        Ledger {
+            profile,
+            ..Default::default()
+        }
+    }
+
+    /// Keeps transactions and dispute records in `store` instead of memory.
+    pub fn with_store(self, store: Arc<dyn LedgerStore>) -> Self {
+        Ledger { store, ..self }
+    }
+
+    pub fn with_tx_scope(self, tx_scope: TxScope) -> Self {
+        Ledger { tx_scope, ..self }
+    }
+
+    /// Evicts transactions that hold nothing under dispute once `window` events passed without
+    /// naming them, folding their applied events into the client's carried-forward balances.
+    pub fn with_dispute_window(self, window: u64) -> Self {
+        Ledger {
+            dispute_window: Some(window.max(1)),
+            ..self
+        }
+    }
+
+    /// Appends every evicted transaction to a CSV file.
+    pub fn with_archive(self, path: &str) -> Result<Self, String> {
+        let file =
+            File::create(path).map_err(|e| format!("Can't create the archive {}: {}", path, e))?;
+        Ok(Ledger {
+            archive: Some(Arc::new(Mutex::new(Writer::from_writer(file)))),
+            ..self
+        })
+    }
+
+    /// Appends every client mismatch to a CSV file.
+    pub fn with_mismatch_report(self, path: &str) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Can't create the mismatch report {}: {}", path, e))?;
+        Ok(Ledger {
+            mismatch_report: Some(Arc::new(Mutex::new(Writer::from_writer(file)))),
+            ..self
+        })
+    }
+
+    pub fn tx_scope(&self) -> TxScope {
+        self.tx_scope
+    }
+
+    pub fn profile(&self) -> &DisputeProfile {
+        &self.profile
+    }
+
+    /// Records the event and returns it as recorded, with its position in the input. Exact
+    /// repeats of a recorded deposit or withdrawal are counted and return nothing to process.
+    /// Dispute events are only checked against the transaction here; they're recorded once the
+    /// processor applies them.
+    pub fn add_event(&self, mut event: Event) -> Result<Option<Event>, String> {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        event.sequence = sequence;
+        self.evict(sequence);
         let id = event.tx;
         let client = event.client;
+        let key = self.key(id, client);
 
         match event.tx_type {
-            EventType::Deposit | EventType::Withdrawal => match self.transactions.entry(id) {
-                dashmap::mapref::entry::Entry::Occupied(_) => {
-                    Err(format!("Transaction with ID {} already exists", id))
+            EventType::Deposit | EventType::Withdrawal => {
+                let amount = event
+                    .amount
+                    .and_then(Decimal::from_f64)
+                    .ok_or_else(|| format!("No amount for a transaction with ID {}", id))?;
+                if amount <= dec!(0) {
+                    return Err(format!("Negative amount for ID {}", id));
                 }
-                dashmap::mapref::entry::Entry::Vacant(entry) => {
-                    if let Some(amount) = event.amount.and_then(Decimal::from_f64) {
-                        if amount <= dec!(0) {
-                            return Err(format!("Negative amount for ID {}", id));
-                        }
-                        let transaction = match event.tx_type {
-                            EventType::Deposit => Transaction::Deposit { amount, client },
-                            EventType::Withdrawal => Transaction::Withdrawal { amount, client },
-                            _ => return Err(format!("Unknown transaction format for ID {}", id)),
-                        };
-                        entry.insert(transaction);
-                        Ok(())
-                    } else {
-                        Err(format!("No amount for a transaction with ID {}", id))
+                let transaction = match event.tx_type {
+                    EventType::Deposit => Transaction::Deposit { amount, client },
+                    EventType::Withdrawal => Transaction::Withdrawal { amount, client },
+                    _ => return Err(format!("Unknown transaction format for ID {}", id)),
+                };
+
+                let entry =
+                    LedgerEntry::new(transaction.clone(), TransactionStatus::Accepted, sequence);
+                match self.store.insert_transaction(key, entry) {
+                    Some(stored) if stored.transaction == transaction => {
+                        self.duplicates.fetch_add(1, Ordering::Relaxed);
+                        Ok(None)
+                    }
+                    Some(_) => Err(format!("Transaction with ID {} already exists", id)),
+                    None => {
+                        self.track(sequence, key);
+                        Ok(Some(event))
                     }
                 }
-            },
-            EventType::Chargeback | EventType::Dispute | EventType::Resolve => {
-                if self.fetch_transaction(id, client).is_none() {
-                    return Err(format!(
-                        "Can't find a transaction with ID {} for client {}",
-                        id, client
-                    ));
+            }
+            EventType::Close => {
+                // The payout's ID is claimed now, so no event submitted later can take it before
+                // the worker gets to the close.
+                let payout = Transaction::Payout {
+                    amount: Decimal::ZERO,
+                    client,
+                };
+                let entry = LedgerEntry::new(payout, TransactionStatus::Accepted, sequence);
+                match self.store.insert_transaction(key, entry) {
+                    Some(_) => Err(format!("Transaction with ID {} already exists", id)),
+                    None => {
+                        self.track(sequence, key);
+                        Ok(Some(event))
+                    }
+                }
+            }
+            EventType::Lock => Ok(Some(event)),
+            EventType::Chargeback
+            | EventType::Dispute
+            | EventType::Resolve
+            | EventType::ChargebackReversal => {
+                match self.fetch_transaction(id, client) {
+                    Some(Transaction::Payout { .. }) => {
+                        return Err(format!("Payout with ID {} can't be disputed", id))
+                    }
+                    Some(_) => {}
+                    None => return Err(self.missing_transaction(&event, sequence)),
                 }
 
-                let dispute_event = match event.tx_type {
-                    EventType::Dispute => DisputeEvent::Dispute,
-                    EventType::Chargeback => DisputeEvent::Chargeback,
-                    EventType::Resolve => DisputeEvent::Resolve,
-                    _ => return Err(format!("Unknown dispute format: {:?}", event.tx_type)),
-                };
+                self.store.update_transaction(key, &mut |entry| {
+                    entry.touched = sequence;
+                    entry.pending += 1;
+                });
+                Ok(Some(event))
+            }
+        }
+    }
 
-                let previous_event = self
-                    .disputes
-                    .get(&id)
-                    .and_then(|f| f.clone().into_iter().last());
+    /// Takes the dispute event through the profile from the state the transaction is in and
+    /// records it, returning the transition taken and the amount it moves. The processor calls
+    /// this as it applies the event, so the history only holds dispute events that reached the
+    /// account. The transaction stays disputed until everything held is resolved or rejected, and
+    /// a resolve that leaves funds held keeps it in the state it was in, so disputing more of it
+    /// isn't taken for re-opening a resolved dispute.
+    pub fn record_dispute(&self, event: &Event) -> Result<(Transition, Decimal), String> {
+        let key = self.key(event.tx, event.client);
+        let entry = match self.store.transaction(key) {
+            Some(entry) if entry.transaction.client() == event.client => entry,
+            _ => return Err(format!("Can't find a transaction with ID {}", event.tx)),
+        };
+        match &entry.status {
+            TransactionStatus::Accepted => {
+                return Err(format!("Transaction {} was never applied", event.tx))
+            }
+            TransactionStatus::Rejected(reason) => {
+                return Err(format!(
+                    "Transaction with ID {} was rejected: {}",
+                    event.tx, reason
+                ))
+            }
+            _ => {}
+        }
 
-                if !self.is_valid_dispute_transition(previous_event.clone(), dispute_event.clone())
-                {
-                    return Err(String::from("Undefined transition"));
-                }
+        let dispute_event = DisputeEvent::try_from(&event.tx_type)?;
+        let records = self.store.disputes(key);
 
-                self.disputes
-                    .entry(id)
-                    .and_modify(|v| {
-                        v.push(dispute_event.clone());
-                    })
-                    .or_insert_with(|| vec![dispute_event.clone()]);
-                Ok(())
+        let transition = self
+            .profile
+            .transition(&entry.state, &dispute_event)
+            .filter(|transition| Self::within_limit(&records, transition))
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "Transaction {} can't {} when {}",
+                    event.tx, dispute_event, entry.state
+                )
+            })?;
+        let amount = self.dispute_amount(&entry.transaction, &records, &transition, event)?;
+        let delta = match transition.effect {
+            Effect::Hold => amount,
+            Effect::Resolve | Effect::Reject => -amount,
+            Effect::Restore | Effect::None => Decimal::ZERO,
+        };
+        let state = match transition.effect {
+            Effect::Resolve if !(entry.disputed + delta).is_zero() => entry.state.clone(),
+            _ => transition.to.clone(),
+        };
+
+        self.store.push_dispute(
+            key,
+            DisputeRecord {
+                event: dispute_event,
+                amount,
+                state: state.clone(),
+                effect: transition.effect,
+                details: DisputeDetails::from(event),
+                sequence: event.sequence,
+            },
+        );
+        self.store.update_transaction(key, &mut |entry| {
+            entry.state = state.clone();
+            entry.disputed += delta;
+            let status = match transition.effect {
+                _ if !entry.disputed.is_zero() => Some(TransactionStatus::Disputed),
+                Effect::Resolve => Some(TransactionStatus::Resolved),
+                Effect::Reject => Some(TransactionStatus::ChargedBack),
+                Effect::Restore => Some(TransactionStatus::ChargebackReversed),
+                Effect::Hold | Effect::None => None,
+            };
+            if let Some(status) = status {
+                entry.status = status;
             }
-        }
+        });
+        Ok((transition, amount))
+    }
+
+    /// Settles the payout the close claimed when it was submitted.
+    pub fn record_payout(&self, id: u32, client: u16, amount: Decimal) -> Result<(), String> {
+        self.store
+            .update_transaction(self.key(id, client), &mut |entry| {
+                entry.transaction = Transaction::Payout { amount, client };
+                entry.status = TransactionStatus::Applied;
+            })
+            .map(|_| ())
+            .ok_or_else(|| format!("Can't find the payout with ID {}", id))
     }
 
     pub fn fetch_transaction(&self, id: u32, client: u16) -> Option<Transaction> {
-        self.transactions.get(&id).and_then(|tx| {
-            let transaction = tx.value();
+        self.store.transaction(self.key(id, client)).and_then(|tx| {
+            let transaction = tx.transaction;
             match transaction {
                 Transaction::Deposit {
                     client: tx_client, ..
                 }
                 | Transaction::Withdrawal {
                     client: tx_client, ..
+                }
+                | Transaction::Payout {
+                    client: tx_client, ..
                 } => {
-                    if *tx_client == client {
-                        Some(transaction.clone())
+                    if tx_client == client {
+                        Some(transaction)
                     } else {
                         None
                     }
@@ -105,16 +697,386 @@ impl Ledger {
         })
     }
 
-    fn is_valid_dispute_transition(
+    /// Looks the transaction up for the client, or by ID alone when IDs are globally unique.
+    pub fn history(&self, id: u32, client: Option<u16>) -> Option<TransactionHistory> {
+        let key = match (self.tx_scope, client) {
+            (TxScope::Global, _) => self.key(id, 0),
+            (TxScope::Client, Some(client)) => self.key(id, client),
+            (TxScope::Client, None) => return None,
+        };
+        self.history_at(key)
+            .filter(|history| client.is_none_or(|client| history.transaction.client() == client))
+    }
+
+    /// Every recorded transaction, by ID.
+    pub fn transactions(&self) -> Vec<TransactionHistory> {
+        self.histories(self.store.transaction_keys())
+    }
+
+    /// Every transaction that ever saw a dispute event, by ID.
+    pub fn disputed(&self) -> Vec<TransactionHistory> {
+        self.histories(self.store.disputed_keys())
+    }
+
+    /// How many exact repeats of recorded transactions were skipped.
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates.load(Ordering::Relaxed)
+    }
+
+    /// How many dispute events named another client's transaction.
+    pub fn mismatches(&self) -> u64 {
+        self.mismatches.load(Ordering::Relaxed)
+    }
+
+    /// How many transactions were evicted from memory.
+    pub fn evicted(&self) -> u64 {
+        self.evicted.load(Ordering::Relaxed)
+    }
+
+    /// The balances carried forward from the client's evicted transactions.
+    pub fn carried(&self, client: u16) -> Option<Rollup> {
+        self.carried.get(&client).map(|rollup| rollup.clone())
+    }
+
+    /// Rolls the client's carried-forward balances up with every applied event still indexed.
+    pub fn rollup(&self, client: u16) -> Rollup {
+        let mut rollup = self.carried(client).unwrap_or_default();
+        for entry in self.client_entries(client) {
+            let on_deposit = matches!(
+                self.fetch_transaction(entry.tx, client),
+                Some(Transaction::Deposit { .. })
+            );
+            rollup.apply(&entry, on_deposit);
+        }
+        rollup
+    }
+
+    /// Whether an operator or a transaction other than `tx` still keeps the client's account
+    /// locked.
+    pub fn locked_by_others(&self, client: u16, tx: u32) -> bool {
+        let rollup = self.rollup(client);
+        rollup.operator_lock || rollup.locked_by.iter().any(|locked_by| *locked_by != tx)
+    }
+
+    /// Writes out what the archive and the mismatch report still buffer.
+    pub fn flush_reports(&self) -> Result<(), String> {
+        if let Some(archive) = &self.archive {
+            archive
+                .lock()
+                .unwrap()
+                .flush()
+                .map_err(|e| format!("Can't write the archive: {}", e))?;
+        }
+        if let Some(report) = &self.mismatch_report {
+            report
+                .lock()
+                .unwrap()
+                .flush()
+                .map_err(|e| format!("Can't write the mismatch report: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// How many events the ledger has been given so far.
+    pub fn sequence(&self) -> u64 {
+        self.sequence.load(Ordering::Relaxed)
+    }
+
+    pub fn index_event(&self, client: u16, tx: u32, event: EventType, amount: Decimal) {
+        self.push_index(client, tx, event, amount, None);
+    }
+
+    pub fn index_dispute(
+        &self,
+        client: u16,
+        tx: u32,
+        event: EventType,
+        amount: Decimal,
+        transition: &Transition,
+    ) {
+        self.push_index(client, tx, event, amount, Some(transition.clone()));
+    }
+
+    /// Records the balances after the event on top of the client's latest ones.
+    fn push_index(
+        &self,
+        client: u16,
+        tx: u32,
+        event: EventType,
+        amount: Decimal,
+        transition: Option<Transition>,
+    ) {
+        let on_deposit = matches!(
+            self.fetch_transaction(tx, client),
+            Some(Transaction::Deposit { .. })
+        );
+        let mut index = self.index.entry(client).or_default();
+        let mut entry = IndexEntry {
+            tx,
+            event,
+            amount,
+            transition,
+            after: index.latest.clone(),
+        };
+        let mut after = entry.after.clone();
+        after.apply(&entry, on_deposit);
+        entry.after = after;
+        index.push(entry);
+    }
+
+    /// The client's account fields right after the event that introduced the transaction, read
+    /// from the balances recorded with it in the index rather than replayed.
+    pub fn balance_at(&self, client: u16, tx: u32) -> Result<Balance, String> {
+        self.index
+            .get(&client)
+            .and_then(|index| Some(Balance::new(client, &index.first(tx)?.after)))
+            .ok_or_else(|| format!("No applied transaction {} for client {}", tx, client))
+    }
+
+    pub fn clients(&self) -> Vec<u16> {
+        self.index.iter().map(|entry| *entry.key()).collect()
+    }
+
+    pub fn client_entries(&self, client: u16) -> Vec<IndexEntry> {
+        self.index
+            .get(&client)
+            .map(|index| index.entries.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn status(&self, id: u32, client: u16) -> Option<TransactionStatus> {
+        self.store
+            .transaction(self.key(id, client))
+            .map(|tx| tx.status)
+    }
+
+    /// Marks a dispute event as taken by the processor, whether it was applied or not, so the
+    /// transaction can be evicted again.
+    pub fn release(&self, event: &Event) {
+        self.store
+            .update_transaction(self.key(event.tx, event.client), &mut |entry| {
+                entry.pending = entry.pending.saturating_sub(1)
+            });
+    }
+
+    /// Forgets a deposit, withdrawal or close that never reached a worker, so it can be submitted
+    /// again. A dispute event that never reached one no longer keeps its transaction from
+    /// eviction.
+    pub fn discard(&self, event: &Event) {
+        if DisputeEvent::try_from(&event.tx_type).is_ok() {
+            self.release(event);
+            return;
+        }
+        if !matches!(
+            event.tx_type,
+            EventType::Deposit | EventType::Withdrawal | EventType::Close
+        ) {
+            return;
+        }
+        if self.status(event.tx, event.client) == Some(TransactionStatus::Accepted) {
+            self.store
+                .remove_transaction(self.key(event.tx, event.client));
+        }
+    }
+
+    pub fn update_status(&self, id: u32, client: u16, status: TransactionStatus) {
+        self.store
+            .update_transaction(self.key(id, client), &mut |tx| tx.status = status.clone());
+    }
+
+    /// Transactions are stored by ID alone, or by client and ID when IDs are scoped per client.
+    fn key(&self, id: u32, client: u16) -> u64 {
+        match self.tx_scope {
+            TxScope::Global => u64::from(id),
+            TxScope::Client => u64::from(client) << 32 | u64::from(id),
+        }
+    }
+
+    fn track(&self, sequence: u64, key: u64) {
+        if self.dispute_window.is_some() {
+            self.recent.lock().unwrap().push_back((sequence, key));
+        }
+    }
+
+    /// Evicts the transactions recorded at least a window ago that hold nothing under dispute,
+    /// have no dispute event waiting for the processor and weren't named since. The rest are checked again a window later.
+    fn evict(&self, sequence: u64) {
+        let Some(window) = self.dispute_window else {
+            return;
+        };
+        let mut recent = self.recent.lock().unwrap();
+        while let Some(&(recorded, key)) = recent.front() {
+            if recorded + window > sequence {
+                break;
+            }
+            recent.pop_front();
+            let settled = self.store.transaction(key).map(|entry| {
+                entry.status != TransactionStatus::Accepted
+                    && entry.disputed.is_zero()
+                    && entry.pending == 0
+                    && entry.touched + window <= sequence
+            });
+            match settled {
+                Some(true) => self.spill(key),
+                Some(false) => recent.push_back((sequence, key)),
+                None => {}
+            }
+        }
+    }
+
+    fn spill(&self, key: u64) {
+        let Some(entry) = self.store.remove_transaction(key) else {
+            return;
+        };
+        let disputes = self.store.remove_disputes(key);
+        let id = key as u32;
+        let client = entry.transaction.client();
+        let on_deposit = matches!(entry.transaction, Transaction::Deposit { .. });
+
+        if let Some(mut index) = self.index.get_mut(&client) {
+            let mut carried = self.carried.entry(client).or_default();
+            for indexed in index.remove(id) {
+                carried.apply(&indexed, on_deposit);
+            }
+        }
+        self.evicted.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(archive) = &self.archive {
+            let archived = ArchivedTransaction {
+                tx: id,
+                client,
+                kind: entry.transaction.kind(),
+                amount: entry.transaction.amount(),
+                status: entry.status.to_string(),
+                dispute_events: disputes.len(),
+            };
+            if let Err(e) = archive.lock().unwrap().serialize(archived) {
+                eprintln!("Can't archive transaction {}: {}", id, e);
+            }
+        }
+    }
+
+    fn histories(&self, mut keys: Vec<u64>) -> Vec<TransactionHistory> {
+        keys.sort_unstable_by_key(|key| (*key as u32, *key >> 32));
+        keys.into_iter()
+            .filter_map(|key| self.history_at(key))
+            .collect()
+    }
+
+    fn history_at(&self, key: u64) -> Option<TransactionHistory> {
+        self.store.transaction(key).map(|entry| TransactionHistory {
+            id: key as u32,
+            transaction: entry.transaction,
+            status: entry.status,
+            disputed: entry.disputed,
+            disputes: self.store.disputes(key),
+        })
+    }
+
+    /// A dispute naming another client's transaction is a possible fraud signal, so it gets its
+    /// own error and is kept for reporting.
+    fn missing_transaction(&self, event: &Event, sequence: u64) -> String {
+        let owner = self
+            .store
+            .transaction(self.key(event.tx, event.client))
+            .map(|tx| tx.transaction.client());
+        match (owner, DisputeEvent::try_from(&event.tx_type)) {
+            (Some(owner), Ok(dispute_event)) => {
+                self.mismatches.fetch_add(1, Ordering::Relaxed);
+                if let Some(report) = &self.mismatch_report {
+                    let mismatch = ClientMismatch {
+                        sequence,
+                        client: event.client,
+                        tx: event.tx,
+                        owner,
+                        event: dispute_event,
+                    };
+                    if let Err(e) = report.lock().unwrap().serialize(mismatch) {
+                        eprintln!(
+                            "Can't report the mismatch on transaction {}: {}",
+                            event.tx, e
+                        );
+                    }
+                }
+                format!(
+                    "Transaction with ID {} doesn't belong to client {}",
+                    event.tx, event.client
+                )
+            }
+            _ => format!(
+                "Can't find a transaction with ID {} for client {}",
+                event.tx, event.client
+            ),
+        }
+    }
+
+    /// Holds default to everything not yet held or charged back, resolves and rejects to
+    /// everything still held, restores to everything charged back and transitions without a
+    /// balance effect to everything not charged back. Explicit amounts can't exceed these
+    /// defaults.
+    fn dispute_amount(
         &self,
-        previous: Option<DisputeEvent>,
-        next: DisputeEvent,
-    ) -> bool {
-        match (previous, next) {
-            (Some(DisputeEvent::Dispute), DisputeEvent::Chargeback | DisputeEvent::Resolve) => true,
-            (None, DisputeEvent::Dispute) => true,
-            (_, _) => false,
+        transaction: &Transaction,
+        records: &[DisputeRecord],
+        transition: &Transition,
+        event: &Event,
+    ) -> Result<Decimal, String> {
+        let sum = |effect: Effect| -> Decimal {
+            records
+                .iter()
+                .filter(|record| record.effect == effect)
+                .map(|record| record.amount)
+                .sum()
+        };
+        let rejected = sum(Effect::Reject);
+        let open = sum(Effect::Hold) - sum(Effect::Resolve) - rejected;
+        let charged_back = rejected - sum(Effect::Restore);
+
+        let next = &transition.event;
+        let outstanding = match transition.effect {
+            Effect::Hold => transaction.amount() - open - charged_back,
+            Effect::Resolve | Effect::Reject => open,
+            Effect::Restore => charged_back,
+            Effect::None => transaction.amount() - charged_back,
+        };
+        let amount = match event.amount {
+            Some(amount) => Decimal::from_f64(amount)
+                .ok_or_else(|| format!("Invalid {} amount for ID {}", next, event.tx))?,
+            None => outstanding,
+        };
+
+        if outstanding <= dec!(0) {
+            return Err(format!("Nothing left to {} for ID {}", next, event.tx));
+        }
+        if amount <= dec!(0) {
+            return Err(format!("Negative {} amount for ID {}", next, event.tx));
+        }
+        if amount > outstanding {
+            return Err(format!(
+                "The {} amount {} exceeds the outstanding {} for ID {}",
+                next, amount, outstanding, event.tx
+            ));
         }
+        Ok(amount)
+    }
+
+    /// Counts how often the transaction already took the transition against its limit.
+    fn within_limit(records: &[DisputeRecord], transition: &Transition) -> bool {
+        let Some(limit) = transition.limit else {
+            return true;
+        };
+        let taken = records
+            .iter()
+            .enumerate()
+            .filter(|(idx, record)| {
+                let from = match idx.checked_sub(1) {
+                    Some(previous) => records[previous].state.as_str(),
+                    None => UNDISPUTED,
+                };
+                from == transition.from && record.event == transition.event
+            })
+            .count();
+        taken < limit
     }
 }
 
@@ -126,7 +1088,35 @@ mod tests {
 
     impl Ledger {
         pub fn count(&self) -> (usize, usize) {
-            (self.transactions.len(), self.disputes.len())
+            self.store.counts()
+        }
+
+        pub fn dispute_state(&self, id: u32, client: u16) -> Option<String> {
+            self.store
+                .transaction(self.key(id, client))
+                .map(|tx| tx.state)
+        }
+
+        /// Adds the event and applies it as the processor would, leaving out the account.
+        /// Returns the amount the event moves.
+        pub fn record(&self, event: Event) -> Result<Decimal, String> {
+            let Some(event) = self.add_event(event)? else {
+                return Ok(Decimal::ZERO);
+            };
+            if DisputeEvent::try_from(&event.tx_type).is_ok() {
+                self.release(&event);
+                return self.record_dispute(&event).map(|(_, amount)| amount);
+            }
+            self.update_status(event.tx, event.client, TransactionStatus::Applied);
+            Ok(event.amount.and_then(Decimal::from_f64).unwrap_or_default())
+        }
+
+        pub fn dispute_events(&self, id: u32) -> Vec<DisputeEvent> {
+            self.store
+                .disputes(u64::from(id))
+                .into_iter()
+                .map(|record| record.event)
+                .collect()
         }
     }
 
@@ -160,9 +1150,10 @@ mod tests {
         assert_eq!(disputes, 0);
 
         assert_eq!(
-            ledger.transactions.get(&1).unwrap().value(),
-            &Transaction::deposit(dec!(10.0), 1)
+            ledger.store.transaction(1).unwrap().transaction,
+            Transaction::deposit(dec!(10.0), 1)
         );
+        assert_eq!(ledger.status(1, 1), Some(TransactionStatus::Accepted));
     }
 
     #[test]
@@ -177,8 +1168,8 @@ mod tests {
         assert_eq!(disputes, 0);
 
         assert_eq!(
-            ledger.transactions.get(&1).unwrap().value(),
-            &Transaction::withdrawal(dec!(10.0), 1)
+            ledger.store.transaction(1).unwrap().transaction,
+            Transaction::withdrawal(dec!(10.0), 1)
         );
     }
 
@@ -203,21 +1194,36 @@ mod tests {
         assert_eq!(result.unwrap_err(), "Transaction with ID 1 already exists");
     }
 
+    #[test]
+    fn test_identical_transaction_is_idempotent() {
+        let ledger = Ledger::default();
+
+        assert!(ledger
+            .add_event(Event::withdrawal(1, 1, 10.0))
+            .unwrap()
+            .is_some());
+        assert_eq!(ledger.add_event(Event::withdrawal(1, 1, 10.0)), Ok(None));
+        assert!(ledger.add_event(Event::deposit(1, 1, 10.0)).is_err());
+        assert!(ledger.add_event(Event::withdrawal(2, 1, 10.0)).is_err());
+
+        assert_eq!(ledger.duplicates(), 1);
+        assert_eq!(ledger.count(), (1, 0));
+    }
+
     #[test]
     fn test_add_dispute() {
         let ledger = Ledger::default();
         let deposit = Event::deposit(1, 1, 10.0);
         let dispute = Event::dispute(1, 1);
 
-        assert!(ledger.add_event(deposit).is_ok());
-        assert!(ledger.add_event(dispute.clone()).is_ok());
+        assert!(ledger.record(deposit).is_ok());
+        assert!(ledger.record(dispute.clone()).is_ok());
 
         let (transactions, disputes) = ledger.count();
         assert_eq!(transactions, 1);
         assert_eq!(disputes, 1);
 
-        let disputes_vec = ledger.disputes.get(&1).unwrap();
-        assert_eq!(disputes_vec.value(), &vec![DisputeEvent::Dispute]);
+        assert_eq!(ledger.dispute_events(1), vec![DisputeEvent::Dispute]);
     }
 
     #[test]
@@ -243,12 +1249,11 @@ mod tests {
         let dispute1 = Event::dispute(1, 1);
         let dispute2 = Event::dispute(1, 1);
 
-        assert!(ledger.add_event(deposit).is_ok());
-        assert!(ledger.add_event(dispute1.clone()).is_ok());
-        assert!(ledger.add_event(dispute2.clone()).is_err()); // Dispute can't go to Dispute
+        assert!(ledger.record(deposit).is_ok());
+        assert!(ledger.record(dispute1.clone()).is_ok());
+        assert!(ledger.record(dispute2.clone()).is_err()); // Dispute can't go to Dispute
 
-        let disputes_vec = ledger.disputes.get(&1).unwrap();
-        assert_eq!(disputes_vec.value(), &vec![DisputeEvent::Dispute]);
+        assert_eq!(ledger.dispute_events(1), vec![DisputeEvent::Dispute]);
     }
 
     #[test]
@@ -257,10 +1262,10 @@ mod tests {
         let deposit = Event::deposit(1, 1, 10.0);
         let resolve = Event::resolve(1, 1);
 
-        assert!(ledger.add_event(deposit).is_ok());
-        assert!(ledger.add_event(resolve).is_err()); // Dispute can't go to Dispute
+        assert!(ledger.record(deposit).is_ok());
+        assert!(ledger.record(resolve).is_err()); // Dispute can't go to Dispute
 
-        assert!(ledger.disputes.get(&1).is_none());
+        assert!(ledger.store.disputes(1).is_empty());
     }
 
     #[test]
@@ -271,14 +1276,14 @@ mod tests {
         let resolve = Event::resolve(1, 1);
         let chargeback = Event::chargeback(1, 1);
 
-        assert!(ledger.add_event(deposit).is_ok());
-        assert!(ledger.add_event(dispute).is_ok());
-        assert!(ledger.add_event(resolve).is_ok());
-        assert!(ledger.add_event(chargeback).is_err());
+        assert!(ledger.record(deposit).is_ok());
+        assert!(ledger.record(dispute).is_ok());
+        assert!(ledger.record(resolve).is_ok());
+        assert!(ledger.record(chargeback).is_err());
 
-        let disputes_vec = ledger.disputes.get(&1).unwrap();
-        assert_eq!(disputes_vec.value().len(), 2);
-        assert_eq!(disputes_vec.value()[1], DisputeEvent::Resolve);
+        let dispute_events = ledger.dispute_events(1);
+        assert_eq!(dispute_events.len(), 2);
+        assert_eq!(dispute_events[1], DisputeEvent::Resolve);
     }
 
     #[test]
@@ -294,29 +1299,326 @@ mod tests {
     }
 
     #[test]
-    fn test_mixed_operations() {
+    fn test_close_with_existing_id() {
         let ledger = Ledger::default();
 
         assert!(ledger.add_event(Event::deposit(1, 1, 10.0)).is_ok());
-        assert!(ledger.add_event(Event::deposit(1, 2, 20.0)).is_ok());
-        assert!(ledger.add_event(Event::deposit(2, 3, 30.0)).is_ok());
+        assert!(ledger.add_event(Event::close(1, 1)).is_err());
+        assert!(ledger.add_event(Event::close(1, 2)).is_ok());
+    }
+
+    #[test]
+    fn test_payout_is_not_disputable() {
+        let ledger = Ledger::default();
+
+        assert!(ledger.record_payout(1, 1, dec!(10.0)).is_err());
+        assert!(ledger.add_event(Event::close(1, 1)).is_ok());
+        assert!(ledger.record_payout(1, 1, dec!(10.0)).is_ok());
+        assert!(ledger.add_event(Event::dispute(1, 1)).is_err());
+    }
+
+    #[test]
+    fn test_dispute_on_rejected_transaction() {
+        let ledger = Ledger::default();
+
+        assert!(ledger.add_event(Event::withdrawal(1, 1, 10.0)).is_ok());
+        ledger.update_status(1, 1, TransactionStatus::Rejected(String::from("No funds")));
+
+        let result = ledger.record(Event::dispute(1, 1));
+        assert_eq!(
+            result.unwrap_err(),
+            "Transaction with ID 1 was rejected: No funds"
+        );
+        assert!(ledger.store.disputes(1).is_empty());
+    }
+
+    #[test]
+    fn test_partial_resolve_keeps_dispute_open() {
+        let ledger = Ledger::default();
+
+        assert!(ledger.record(Event::deposit(1, 1, 30.0)).is_ok());
+        assert!(ledger
+            .record(Event::dispute(1, 1).with_amount(10.0))
+            .is_ok());
+        assert!(ledger.record(Event::resolve(1, 1).with_amount(5.0)).is_ok());
+        assert_eq!(ledger.dispute_state(1, 1), Some(String::from("disputed")));
+        assert_eq!(
+            ledger.record(Event::dispute(1, 1).with_amount(10.0)),
+            Ok(dec!(10))
+        );
+        assert_eq!(ledger.record(Event::resolve(1, 1)), Ok(dec!(15)));
+        assert_eq!(ledger.dispute_state(1, 1), Some(String::from("resolved")));
+        assert_eq!(
+            ledger.record(Event::dispute(1, 1)),
+            Err(String::from("Transaction 1 can't dispute when resolved"))
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let ledger = Ledger::default();
+
+        assert!(ledger.history(1, None).is_none());
+        assert!(ledger.record(Event::deposit(1, 1, 10.0)).is_ok());
+        assert!(ledger.record(Event::dispute(1, 1)).is_ok());
+        assert!(ledger.record(Event::resolve(1, 1)).is_ok());
+
+        assert_eq!(
+            ledger.history(1, None),
+            Some(TransactionHistory {
+                id: 1,
+                transaction: Transaction::deposit(dec!(10.0), 1),
+                status: TransactionStatus::Resolved,
+                disputed: dec!(0),
+                disputes: vec![
+                    DisputeRecord {
+                        event: DisputeEvent::Dispute,
+                        amount: dec!(10.0),
+                        state: String::from("disputed"),
+                        effect: Effect::Hold,
+                        details: DisputeDetails::default(),
+                        sequence: 2,
+                    },
+                    DisputeRecord {
+                        event: DisputeEvent::Resolve,
+                        amount: dec!(10.0),
+                        state: String::from("resolved"),
+                        effect: Effect::Resolve,
+                        details: DisputeDetails::default(),
+                        sequence: 3,
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_dispute_amount_is_recorded() {
+        let ledger = Ledger::default();
+
+        assert!(ledger.record(Event::deposit(1, 1, 10.0)).is_ok());
+        assert_eq!(ledger.record(Event::dispute(1, 1)), Ok(dec!(10)));
+    }
+
+    #[test]
+    fn test_partial_disputes() {
+        let ledger = Ledger::default();
+
+        assert!(ledger.record(Event::deposit(1, 1, 10.0)).is_ok());
+        assert!(ledger.record(Event::dispute(1, 1).with_amount(3.0)).is_ok());
+        assert!(ledger.record(Event::dispute(1, 1).with_amount(4.0)).is_ok());
+        assert_eq!(
+            ledger.record(Event::dispute(1, 1).with_amount(4.0)),
+            Err(String::from(
+                "The dispute amount 4 exceeds the outstanding 3 for ID 1"
+            ))
+        );
+        assert!(ledger
+            .record(Event::resolve(1, 1).with_amount(8.0))
+            .is_err());
+        assert!(ledger.record(Event::resolve(1, 1).with_amount(2.0)).is_ok());
+        assert_eq!(ledger.record(Event::chargeback(1, 1)), Ok(dec!(5)));
+        assert!(ledger.record(Event::resolve(1, 1)).is_err());
+
+        let amounts: Vec<Decimal> = ledger
+            .history(1, None)
+            .unwrap()
+            .disputes
+            .iter()
+            .map(|record| record.amount)
+            .collect();
+        assert_eq!(amounts, vec![dec!(3), dec!(4), dec!(2), dec!(5)]);
+    }
+
+    #[test]
+    fn test_dispute_amount_validation() {
+        let ledger = Ledger::default();
+
+        assert!(ledger.record(Event::deposit(1, 1, 10.0)).is_ok());
+        assert!(ledger
+            .record(Event::dispute(1, 1).with_amount(-1.0))
+            .is_err());
+        assert!(ledger
+            .record(Event::dispute(1, 1).with_amount(11.0))
+            .is_err());
+        assert!(ledger.store.disputes(1).is_empty());
+    }
+
+    #[test]
+    fn test_redispute_after_resolve() {
+        let ledger = Ledger::new(DisputeProfile::default().with_reopen_limit(2).unwrap());
+
+        assert!(ledger.record(Event::deposit(1, 1, 10.0)).is_ok());
+        for _ in 0..3 {
+            assert!(ledger.record(Event::dispute(1, 1)).is_ok());
+            assert!(ledger.record(Event::resolve(1, 1)).is_ok());
+        }
+        assert!(ledger.record(Event::dispute(1, 1)).is_err());
+
+        assert_eq!(ledger.dispute_events(1).len(), 6);
+    }
+
+    #[test]
+    fn test_redispute_of_resolved_part() {
+        let ledger = Ledger::new(DisputeProfile::default().with_reopen_limit(1).unwrap());
+
+        assert!(ledger.record(Event::deposit(1, 1, 10.0)).is_ok());
+        assert!(ledger.record(Event::dispute(1, 1)).is_ok());
+        assert!(ledger.record(Event::resolve(1, 1).with_amount(4.0)).is_ok());
+        assert!(ledger
+            .record(Event::dispute(1, 1).with_amount(5.0))
+            .is_err());
+        assert_eq!(ledger.record(Event::dispute(1, 1)), Ok(dec!(4)));
+    }
+
+    #[test]
+    fn test_chargeback_reversal() {
+        let ledger = Ledger::default();
+
+        assert!(ledger.record(Event::deposit(1, 1, 10.0)).is_ok());
+        assert!(ledger.record(Event::dispute(1, 1)).is_ok());
+        assert_eq!(
+            ledger.record(Event::chargeback_reversal(1, 1)),
+            Err(String::from(
+                "Transaction 1 can't chargeback_reversal when disputed"
+            ))
+        );
+        assert!(ledger
+            .record(Event::chargeback(1, 1).with_amount(6.0))
+            .is_ok());
+        assert!(ledger
+            .record(Event::chargeback_reversal(1, 1).with_amount(7.0))
+            .is_err());
+        assert_eq!(ledger.record(Event::chargeback_reversal(1, 1)), Ok(dec!(6)));
+        assert!(ledger.record(Event::chargeback_reversal(1, 1)).is_err());
+        assert!(ledger.record(Event::dispute(1, 1)).is_err());
+
+        assert_eq!(
+            ledger.dispute_events(1),
+            vec![
+                DisputeEvent::Dispute,
+                DisputeEvent::Chargeback,
+                DisputeEvent::ChargebackReversal
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dispute_for_another_client() {
+        let path = std::env::temp_dir().join(format!("mismatches-{}.csv", std::process::id()));
+        let ledger = Ledger::default()
+            .with_mismatch_report(path.to_str().unwrap())
+            .unwrap();
+
+        assert!(ledger.add_event(Event::deposit(1, 1, 10.0)).is_ok());
+        assert_eq!(
+            ledger.add_event(Event::dispute(2, 1)),
+            Err(String::from(
+                "Transaction with ID 1 doesn't belong to client 2"
+            ))
+        );
+        assert!(ledger.add_event(Event::dispute(2, 3)).is_err());
+
+        assert_eq!(ledger.mismatches(), 1);
+        ledger.flush_reports().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "sequence,client,tx,owner,event\n2,2,1,1,dispute\n"
+        );
+        std::fs::remove_file(path).unwrap();
+        assert!(ledger.history(1, Some(2)).is_none());
+        assert!(ledger.history(1, Some(1)).is_some());
+    }
+
+    #[test]
+    fn test_tx_ids_scoped_per_client() {
+        let ledger = Ledger::default().with_tx_scope(TxScope::Client);
+
+        assert!(ledger.record(Event::deposit(1, 1, 10.0)).is_ok());
+        assert!(ledger.record(Event::deposit(2, 1, 20.0)).is_ok());
+        assert!(ledger.record(Event::deposit(2, 1, 25.0)).is_err());
+        assert_eq!(ledger.record(Event::dispute(2, 1)), Ok(dec!(20)));
+
+        assert_eq!(
+            ledger.fetch_transaction(1, 1),
+            Some(Transaction::deposit(dec!(10.0), 1))
+        );
+        assert_eq!(ledger.history(1, Some(2)).unwrap().disputes.len(), 1);
+        assert!(ledger.history(1, Some(1)).unwrap().disputes.is_empty());
+        assert!(ledger.history(1, None).is_none());
+        assert_eq!(ledger.mismatches(), 0);
+    }
+
+    #[test]
+    fn test_dispute_window_evicts_settled_transactions() {
+        let ledger = Ledger::default().with_dispute_window(2);
+        let apply = |event: Event| {
+            let event = ledger.add_event(event).unwrap().unwrap();
+            ledger.update_status(event.tx, event.client, TransactionStatus::Applied);
+            ledger.index_event(
+                event.client,
+                event.tx,
+                event.tx_type,
+                Decimal::from_f64(event.amount.unwrap()).unwrap(),
+            );
+        };
+
+        apply(Event::deposit(1, 1, 10.0));
+        apply(Event::deposit(1, 2, 20.0));
+        ledger.record(Event::dispute(1, 2)).unwrap();
+        apply(Event::deposit(1, 3, 5.0));
+        apply(Event::deposit(1, 4, 5.0));
+        apply(Event::deposit(1, 5, 5.0));
+
+        assert_eq!(ledger.evicted(), 2);
+        assert_eq!(ledger.fetch_transaction(1, 1), None);
+        assert_eq!(ledger.fetch_transaction(3, 1), None);
+        assert!(ledger.fetch_transaction(2, 1).is_some());
+        assert_eq!(
+            ledger.carried(1),
+            Some(Rollup {
+                available: dec!(15),
+                deposits: dec!(15),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            ledger.rollup(1).available,
+            dec!(45),
+            "evicted and indexed deposits both count"
+        );
+        assert_eq!(
+            ledger.add_event(Event::dispute(1, 1)),
+            Err(String::from(
+                "Can't find a transaction with ID 1 for client 1"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_mixed_operations() {
+        let ledger = Ledger::default();
+
+        assert!(ledger.record(Event::deposit(1, 1, 10.0)).is_ok());
+        assert!(ledger.record(Event::deposit(1, 2, 20.0)).is_ok());
+        assert!(ledger.record(Event::deposit(2, 3, 30.0)).is_ok());
 
-        assert!(ledger.add_event(Event::withdrawal(1, 4, 5.0)).is_ok());
-        assert!(ledger.add_event(Event::withdrawal(2, 5, 10.0)).is_ok());
+        assert!(ledger.record(Event::withdrawal(1, 4, 5.0)).is_ok());
+        assert!(ledger.record(Event::withdrawal(2, 5, 10.0)).is_ok());
 
-        assert!(ledger.add_event(Event::dispute(1, 1)).is_ok());
-        assert!(ledger.add_event(Event::dispute(1, 2)).is_ok());
-        assert!(ledger.add_event(Event::dispute(2, 3)).is_ok());
+        assert!(ledger.record(Event::dispute(1, 1)).is_ok());
+        assert!(ledger.record(Event::dispute(1, 2)).is_ok());
+        assert!(ledger.record(Event::dispute(2, 3)).is_ok());
 
-        assert!(ledger.add_event(Event::resolve(1, 1)).is_ok());
-        assert!(ledger.add_event(Event::chargeback(1, 2)).is_ok());
+        assert!(ledger.record(Event::resolve(1, 1)).is_ok());
+        assert!(ledger.record(Event::chargeback(1, 2)).is_ok());
 
         let (transactions, disputes) = ledger.count();
         assert_eq!(transactions, 5);
         assert_eq!(disputes, 3);
 
-        assert_eq!(ledger.disputes.get(&1).unwrap().value().len(), 2);
-        assert_eq!(ledger.disputes.get(&2).unwrap().value().len(), 2);
-        assert_eq!(ledger.disputes.get(&3).unwrap().value().len(), 1);
+        assert_eq!(ledger.store.disputes(1).len(), 2);
+        assert_eq!(ledger.store.disputes(2).len(), 2);
+        assert_eq!(ledger.store.disputes(3).len(), 1);
     }
 }
@@ -0,0 +1,199 @@
+use csv::{ReaderBuilder, Trim};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs::File, io::Read};
+
+use crate::ledger::DisputeEvent;
+
+/// The state of a transaction that was never disputed.
+pub const UNDISPUTED: &str = "undisputed";
+
+const DEFAULT_PROFILE: &str = include_str!("../profiles/default.csv");
+
+/// The balance effect of a transition, see `Account::hold`, `Account::resolve`,
+/// `Account::reject` and `Account::restore`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    Hold,
+    Resolve,
+    Reject,
+    Restore,
+    None,
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Effect::Hold => write!(f, "hold"),
+            Effect::Resolve => write!(f, "resolve"),
+            Effect::Reject => write!(f, "reject"),
+            Effect::Restore => write!(f, "restore"),
+            Effect::None => write!(f, "none"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountChange {
+    Lock,
+    Unlock,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Transition {
+    pub from: String,
+    pub event: DisputeEvent,
+    pub to: String,
+    pub effect: Effect,
+    pub account: Option<AccountChange>,
+    /// How many times a single transaction may take the transition, unlimited when empty.
+    pub limit: Option<usize>,
+}
+
+/// A dispute lifecycle: the transitions a dispute event may take from each state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisputeProfile {
+    transitions: Vec<Transition>,
+}
+
+impl Default for DisputeProfile {
+    fn default() -> Self {
+        DisputeProfile::parse(DEFAULT_PROFILE.as_bytes()).expect("Invalid default dispute profile")
+    }
+}
+
+impl DisputeProfile {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("Can't open the dispute profile {}: {}", path, e))?;
+        DisputeProfile::parse(file)
+    }
+
+    pub fn parse<R: Read>(reader: R) -> Result<Self, String> {
+        let mut rdr = ReaderBuilder::new()
+            .trim(Trim::All)
+            .comment(Some(b'#'))
+            .from_reader(reader);
+
+        let mut transitions: Vec<Transition> = vec![];
+        for result in rdr.deserialize() {
+            let transition: Transition =
+                result.map_err(|e| format!("Invalid dispute profile: {}", e))?;
+            if transitions
+                .iter()
+                .any(|other| other.from == transition.from && other.event == transition.event)
+            {
+                return Err(format!(
+                    "Duplicate {} transition from {}",
+                    transition.event, transition.from
+                ));
+            }
+            transitions.push(transition);
+        }
+
+        if !transitions
+            .iter()
+            .any(|transition| transition.from == UNDISPUTED)
+        {
+            return Err(format!("No transition from {}", UNDISPUTED));
+        }
+        Ok(DisputeProfile { transitions })
+    }
+
+    pub fn transition(&self, from: &str, event: &DisputeEvent) -> Option<&Transition> {
+        self.transitions
+            .iter()
+            .find(|transition| transition.from == from && transition.event == *event)
+    }
+
+    /// Caps how many times a resolved dispute may be opened again, for profiles that re-open
+    /// disputes from a `resolved` state.
+    pub fn with_reopen_limit(mut self, limit: usize) -> Result<Self, String> {
+        let reopen = self
+            .transitions
+            .iter_mut()
+            .find(|transition| {
+                transition.from == "resolved" && transition.event == DisputeEvent::Dispute
+            })
+            .ok_or_else(|| {
+                String::from("The dispute profile has no dispute transition from resolved")
+            })?;
+        reopen.limit = Some(limit);
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_profile() {
+        let profile = DisputeProfile::default();
+
+        let chargeback = profile
+            .transition("disputed", &DisputeEvent::Chargeback)
+            .unwrap();
+        assert_eq!(chargeback.to, "charged_back");
+        assert_eq!(chargeback.effect, Effect::Reject);
+        assert_eq!(chargeback.account, Some(AccountChange::Lock));
+        assert!(profile
+            .transition(UNDISPUTED, &DisputeEvent::Resolve)
+            .is_none());
+        assert_eq!(
+            profile
+                .transition("resolved", &DisputeEvent::Dispute)
+                .unwrap()
+                .limit,
+            Some(0)
+        );
+        assert_eq!(
+            profile
+                .with_reopen_limit(2)
+                .unwrap()
+                .transition("resolved", &DisputeEvent::Dispute)
+                .unwrap()
+                .limit,
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_parse_custom_profile() {
+        let profile = DisputeProfile::parse(
+            "from, event, to, effect, account, limit
+            undisputed, dispute, retrieval, none, ,
+            retrieval, dispute, disputed, hold, , 1"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        let retrieval = profile
+            .transition(UNDISPUTED, &DisputeEvent::Dispute)
+            .unwrap();
+        assert_eq!(retrieval.effect, Effect::None);
+        assert_eq!(retrieval.account, None);
+        assert_eq!(retrieval.limit, None);
+        assert_eq!(
+            profile.with_reopen_limit(1),
+            Err(String::from(
+                "The dispute profile has no dispute transition from resolved"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let header = "from, event, to, effect, account, limit\n";
+        let parse = |rows: &str| DisputeProfile::parse(format!("{}{}", header, rows).as_bytes());
+
+        assert!(parse("undisputed, dispute, disputed, freeze, ,").is_err());
+        assert!(parse("undisputed, review, disputed, hold, ,").is_err());
+        assert!(parse("disputed, resolve, resolved, resolve, ,").is_err());
+        assert_eq!(
+            parse("undisputed, dispute, disputed, hold, ,\nundisputed, dispute, open, hold, ,"),
+            Err(String::from("Duplicate dispute transition from undisputed"))
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use csv::{ReaderBuilder, StringRecord, Trim};
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use crate::{engine::Engine, event::Event};
+
+const COLUMNS: [&str; 7] = [
+    "type", "client", "tx", "amount", "reason", "case_id", "notes",
+];
+
+/// How often idle connections and the accept loops check for shutdown.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub fn listen(addr: &str, engine: &Engine, stop: &AtomicBool) -> Result<(), String> {
+    let listener =
+        TcpListener::bind(addr).map_err(|e| format!("Can't listen on {}: {}", addr, e))?;
+    eprintln!("Listening on {}", addr);
+    accept(listener, engine, stop)
+}
+
+/// Serves every connection on its own thread until `stop` is set, then waits for the
+/// connections to finish the line they're on.
+fn accept(listener: TcpListener, engine: &Engine, stop: &AtomicBool) -> Result<(), String> {
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Can't poll the listener: {}", e))?;
+
+    thread::scope(|scope| {
+        while !stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    scope.spawn(move || {
+                        if let Err(e) = handle(stream, engine, stop) {
+                            eprintln!("Connection from {} failed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(e) => eprintln!("Can't accept a connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Answers every line with `ack` once the event is submitted, or `nack` and the reason.
+fn handle(stream: TcpStream, engine: &Engine, stop: &AtomicBool) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let writer = stream.try_clone()?;
+    answer_lines(stream, writer, stop, |line| {
+        Some(match submit(engine, line) {
+            Ok(()) => String::from("ack"),
+            Err(reason) => format!("nack {}", reason),
+        })
+    })
+}
+
+/// Writes the answer to every trimmed line read until the end of the input or until `stop` is
+/// set, which is checked whenever a read times out. Lines without an answer go unanswered.
+pub fn answer_lines(
+    reader: impl Read,
+    mut writer: impl Write,
+    stop: &AtomicBool,
+    mut answer: impl FnMut(&str) -> Option<String>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    while !stop.load(Ordering::Relaxed) {
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {
+                if let Some(reply) = answer(line.trim()) {
+                    writeln!(writer, "{}", reply)?;
+                }
+                line.clear();
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Blank lines and the header row are acknowledged without submitting anything.
+fn submit(engine: &Engine, line: &str) -> Result<(), String> {
+    if line.is_empty() || line.starts_with("type") {
+        return Ok(());
+    }
+    engine.submit_event(parse(line)?)
+}
+
+fn parse(line: &str) -> Result<Event, String> {
+    let record = ReaderBuilder::new()
+        .has_headers(false)
+        .trim(Trim::All)
+        .from_reader(line.as_bytes())
+        .records()
+        .next()
+        .ok_or_else(|| String::from("Empty line"))?
+        .map_err(|e| format!("Can't parse: {}", e))?;
+    let headers = StringRecord::from(&COLUMNS[..record.len().min(COLUMNS.len())]);
+    record
+        .deserialize(Some(&headers))
+        .map_err(|e| format!("Can't parse: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ledger::Ledger, store::MemoryChart};
+    use rust_decimal::dec;
+    use std::{io::Read, net::Shutdown, sync::Arc};
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(parse("deposit, 1, 2, 3.5"), Ok(Event::deposit(1, 2, 3.5)));
+        assert_eq!(parse("dispute, 1, 2,"), Ok(Event::dispute(1, 2)));
+        assert_eq!(
+            parse("dispute, 1, 2, , fraud"),
+            Ok(Event::dispute(1, 2).with_reason("fraud"))
+        );
+        assert!(parse("deposit, one, 2, 3.5").is_err());
+    }
+
+    #[test]
+    fn test_acknowledge_lines() {
+        let mut engine = Engine::new(Ledger::default(), Arc::new(MemoryChart::default()));
+        let handles = engine.start_workers();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let stop = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            scope.spawn(|| accept(listener, &engine, &stop).unwrap());
+
+            let replies: Vec<String> = [
+                "type, client, tx, amount\ndeposit, 1, 1, 10.0\n",
+                "deposit, 2, 2, 4.0\nwithdrawal, 2, 3\ndispute, 2, 9,\n",
+            ]
+            .into_iter()
+            .map(|lines| {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                stream.write_all(lines.as_bytes()).unwrap();
+                stream.shutdown(Shutdown::Write).unwrap();
+                let mut replies = String::new();
+                stream.read_to_string(&mut replies).unwrap();
+                replies
+            })
+            .collect();
+
+            assert_eq!(replies[0], "ack\nack\n");
+            assert_eq!(
+                replies[1],
+                "ack\nnack No amount for a transaction with ID 3\n\
+                 nack Can't find a transaction with ID 9 for client 2\n"
+            );
+            stop.store(true, Ordering::Relaxed);
+        });
+
+        engine.shutdown();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(engine.core.chart.account(1).unwrap().available(), dec!(10));
+        assert_eq!(engine.core.chart.account(2).unwrap().available(), dec!(4));
+    }
+}
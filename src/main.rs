@@ -1,16 +1,45 @@
 mod account;
+mod admin;
+mod async_engine;
+mod cli;
+mod disputes;
 mod engine;
 mod event;
+mod export;
+mod journal;
 mod ledger;
+mod lifecycle;
+mod listener;
 mod processor;
+mod replay;
 mod resources;
+mod server;
+mod statement;
+mod store;
+mod summary;
+mod verify;
 mod worker;
 
+use async_engine::AsyncEngine;
+use cli::{Cli, Command, Runtime};
 use csv::Writer;
-use engine::Engine;
+use engine::{Engine, EngineCore};
 use event::Event;
+use ledger::{Ledger, TxScope};
+use lifecycle::DisputeProfile;
+use store::{AccountStore, MemoryChart, SledStore};
+use summary::Summary;
 
-use std::sync::mpsc;
+use anyhow::Context;
+use serde::Serialize;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::fs::File;
+use std::io::Write;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Arc,
+};
+use std::thread::{self, JoinHandle};
 use std::{env, process};
 
 const QUEUE_CAPACITY: usize = 100;
@@ -23,40 +52,353 @@ enum StreamEvent {
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() <= 1 {
-        eprintln!("No arguments provided");
-        process::exit(1);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (command, runtime, ledger, chart) = match Cli::parse(&args).and_then(|cli| {
+        let (ledger, chart) = storage(&cli)?;
+        Ok((cli.command, cli.runtime, ledger, chart))
+    }) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1);
+        }
+    };
+
+    match command {
+        Command::Process {
+            input,
+            summary,
+            dispute_report,
+            open_disputes,
+            export_sqlite,
+            ..
+        } => {
+            let core = load(&input, runtime, ledger, chart);
+            write_accounts(&core);
+
+            if let Some(target) = summary {
+                if let Err(e) = write_summary(&core, &target) {
+                    eprintln!("Error {:?}", e);
+                    process::exit(1);
+                }
+            }
+            if let Some(target) = dispute_report {
+                if let Err(e) = write_rows(&target, disputes::by_reason(&core.ledger)) {
+                    eprintln!("Error {:?}", e);
+                    process::exit(1);
+                }
+            }
+            if let Some(target) = open_disputes {
+                if let Err(e) = write_rows(&target, disputes::open(&core.ledger)) {
+                    eprintln!("Error {:?}", e);
+                    process::exit(1);
+                }
+            }
+            if let Some(path) = export_sqlite {
+                if let Err(message) = export::sqlite(&path, &core.ledger, core.chart.as_ref()) {
+                    eprintln!("{}", message);
+                    process::exit(1);
+                }
+            }
+        }
+        Command::History { tx, client, state } => {
+            if ledger.tx_scope() == TxScope::Client && client.is_none() {
+                eprintln!("Transaction IDs are scoped per client, --client is required");
+                process::exit(1);
+            }
+            let core = load(&state, runtime, ledger, chart);
+
+            match core.ledger.history(tx, client) {
+                Some(history) => {
+                    println!("transaction {}: {}", history.id, history.transaction);
+                    println!("status: {}", history.status);
+                    for (idx, event) in history.disputes.iter().enumerate() {
+                        println!("{}. {}", idx + 1, event);
+                    }
+                }
+                None => {
+                    eprintln!("Can't find a transaction with ID {}", tx);
+                    process::exit(1);
+                }
+            }
+        }
+        Command::Statement {
+            client,
+            state,
+            at: Some(tx),
+        } => {
+            let core = load(&state, runtime, ledger, chart);
+
+            match core.ledger.balance_at(client, tx) {
+                Ok(balance) => {
+                    let mut wtr = Writer::from_writer(std::io::stdout());
+                    wtr.serialize(balance).unwrap();
+                    wtr.flush().unwrap();
+                }
+                Err(message) => {
+                    eprintln!("{}", message);
+                    process::exit(1);
+                }
+            }
+        }
+        Command::Statement {
+            client,
+            state,
+            at: None,
+        } => {
+            let core = load(&state, runtime, ledger, chart);
+
+            match statement::build(&core.ledger, client) {
+                Ok(lines) => {
+                    let mut wtr = Writer::from_writer(std::io::stdout());
+                    for line in lines {
+                        wtr.serialize(line).unwrap();
+                    }
+                    wtr.flush().unwrap();
+                }
+                Err(message) => {
+                    eprintln!("{}", message);
+                    process::exit(1);
+                }
+            }
+        }
+        Command::Serve { addr } => {
+            let stop = stop_on_signals();
+            let mut engine = Engine::new(ledger, chart);
+            let handles = engine.start_workers();
+
+            if let Err(message) = server::serve(&addr, &engine, &stop) {
+                eprintln!("{}", message);
+                process::exit(1);
+            }
+            drain(&engine, handles);
+            write_accounts(&engine.core);
+        }
+        Command::Listen { tcp, admin } => {
+            let stop = stop_on_signals();
+            let mut engine = Engine::new(ledger, chart);
+            let handles = engine.start_workers();
+
+            let result = thread::scope(|scope| {
+                if let Some(path) = &admin {
+                    scope.spawn(|| {
+                        if let Err(message) = admin::serve(path, &engine, &stop) {
+                            eprintln!("{}", message);
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                    });
+                }
+                let result = listener::listen(&tcp, &engine, &stop);
+                // Stops the admin socket as well when the listener fails.
+                stop.store(true, Ordering::Relaxed);
+                result
+            });
+            if let Err(message) = result {
+                eprintln!("{}", message);
+                process::exit(1);
+            }
+            drain(&engine, handles);
+            write_accounts(&engine.core);
+        }
+        Command::Replay { log, until } => match replay::replay(&log, until, ledger, chart) {
+            Ok(core) => write_accounts(&core),
+            Err(message) => {
+                eprintln!("{}", message);
+                process::exit(1);
+            }
+        },
+        Command::Verify { state } => {
+            let core = load(&state, runtime, ledger, chart);
+
+            let discrepancies = verify::reconcile(&core.ledger, core.chart.as_ref());
+            if discrepancies.is_empty() {
+                eprintln!("All {} accounts reconciled", core.chart.accounts().len());
+                return;
+            }
+
+            let mut wtr = Writer::from_writer(std::io::stdout());
+            for discrepancy in discrepancies {
+                wtr.serialize(discrepancy).unwrap();
+            }
+            wtr.flush().unwrap();
+            process::exit(1);
+        }
     }
+}
 
-    let (tx, rx) = mpsc::channel::<StreamEvent>();
+/// Returns a flag that SIGINT and SIGTERM set.
+fn stop_on_signals() -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        if let Err(e) = signal_hook::flag::register(signal, stop.clone()) {
+            eprintln!("Can't handle signal {}: {}", signal, e);
+            process::exit(1);
+        }
+    }
+    stop
+}
 
-    let mut engine = Engine::new();
-    let handles = engine.start_workers();
+fn write_accounts(core: &EngineCore) {
+    let mut wtr = Writer::from_writer(std::io::stdout());
+    for account in core.chart.accounts() {
+        wtr.serialize(account).unwrap();
+    }
+    wtr.flush().unwrap();
+}
+
+/// Opens a report target, where `-` stands for stderr.
+fn report_output(target: &str) -> anyhow::Result<Box<dyn Write>> {
+    Ok(match target {
+        "-" => Box::new(std::io::stderr()),
+        path => {
+            Box::new(File::create(path).with_context(|| format!("Failed to write to {}", path))?)
+        }
+    })
+}
+
+fn write_summary(core: &EngineCore, target: &str) -> anyhow::Result<()> {
+    let mut wtr = Writer::from_writer(report_output(target)?);
+    wtr.write_record(["metric", "value"])?;
+    for row in Summary::build(&core.ledger, core.chart.as_ref()).rows() {
+        wtr.serialize(row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+fn write_rows<T: Serialize>(target: &str, rows: Vec<T>) -> anyhow::Result<()> {
+    let mut wtr = Writer::from_writer(report_output(target)?);
+    for row in rows {
+        wtr.serialize(row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Keeps the ledger and the chart in memory, or in the persistent `--store`.
+fn storage(cli: &Cli) -> Result<(Ledger, Arc<dyn AccountStore>), String> {
+    let mut ledger = Ledger::new(dispute_profile(cli)?).with_tx_scope(cli.tx_scope);
+    let chart: Arc<dyn AccountStore> = match &cli.store {
+        Some(path) => {
+            let store = SledStore::open(path)?;
+            if !store.is_empty() && cli.command.reads_run_state() {
+                return Err(format!(
+                    "The store {} holds an earlier run, whose balances and counters aren't kept; \
+                     verify, statement, --summary, --open-disputes and --export-sqlite need an \
+                     empty one",
+                    path
+                ));
+            }
+            if !store.is_empty() && cli.dispute_window.is_some() {
+                return Err(format!(
+                    "The store {} holds an earlier run, whose transactions can't be evicted; \
+                     --dispute-window needs an empty one",
+                    path
+                ));
+            }
+            ledger = ledger.with_store(Arc::new(store.clone()));
+            Arc::new(store)
+        }
+        None => Arc::new(MemoryChart::default()),
+    };
+    if let Some(window) = cli.dispute_window {
+        ledger = ledger.with_dispute_window(window);
+    }
+    if let Some(path) = &cli.archive {
+        ledger = ledger.with_archive(path)?;
+    }
+    // Mismatches are written as they're found, so they aren't kept for the whole run.
+    if let Command::Process {
+        mismatches: Some(path),
+        ..
+    } = &cli.command
+    {
+        ledger = ledger.with_mismatch_report(path)?;
+    }
+    Ok((ledger, chart))
+}
+
+fn dispute_profile(cli: &Cli) -> Result<DisputeProfile, String> {
+    let profile = match &cli.dispute_profile {
+        Some(path) => DisputeProfile::load(path)?,
+        None => DisputeProfile::default(),
+    };
+    match cli.dispute_cycles {
+        Some(cycles) => profile.with_reopen_limit(cycles),
+        None => Ok(profile),
+    }
+}
 
-    if let Err(e) = resources::CsvResource::new(tx).parse(&args[1]) {
+fn load(path: &str, runtime: Runtime, ledger: Ledger, chart: Arc<dyn AccountStore>) -> EngineCore {
+    let (tx, rx) = mpsc::channel::<StreamEvent>();
+
+    if let Err(e) = resources::CsvResource::new(tx).parse(path) {
         eprintln!("Error {:?}", e);
         process::exit(1);
     }
+    let events = rx.into_iter().map_while(|event| match event {
+        StreamEvent::Value(event) => Some(event),
+        StreamEvent::EndOfStream => None,
+    });
 
-    for event in rx {
-        match event {
-            StreamEvent::EndOfStream => break,
-            StreamEvent::Value(event) => {
-                let _ = engine.submit_event(event.clone());
+    match runtime {
+        Runtime::Threads => {
+            let mut engine = Engine::new(ledger, chart);
+            let handles = engine.start_workers();
+            for event in events {
+                // Rejections are recorded in the ledger, so the load goes on past them.
+                let _ = engine.submit_waiting(event);
             }
+            drain(&engine, handles);
+            engine.core
+        }
+        Runtime::Tokio => load_async(events, ledger, chart),
+    }
+}
+
+/// Feeds the events to the async engine one at a time, each once the one before it has been
+/// applied or rejected.
+fn load_async(
+    events: impl Iterator<Item = Event>,
+    ledger: Ledger,
+    chart: Arc<dyn AccountStore>,
+) -> EngineCore {
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Can't start the tokio runtime: {}", e);
+            process::exit(1);
+        }
+    };
+    let engine = {
+        let _guard = runtime.enter();
+        AsyncEngine::start(ledger, chart)
+    };
+    runtime.block_on(async {
+        for event in events {
+            // Rejections are recorded in the ledger, so only the wait for the outcome matters.
+            engine.submit(event).await;
         }
+    });
+    let core = runtime.block_on(engine.shutdown());
+
+    if let Err(message) = core.ledger.flush_reports() {
+        eprintln!("{}", message);
+        process::exit(1);
     }
+    core
+}
 
+/// Lets the workers apply every queued event and stop.
+fn drain(engine: &Engine, handles: Vec<JoinHandle<()>>) {
     engine.shutdown();
 
     for handle in handles {
         handle.join().unwrap();
     }
 
-    let mut wtr = Writer::from_writer(std::io::stdout());
-    for account in engine.core.chart.iter() {
-        wtr.serialize(account.clone()).unwrap();
+    if let Err(message) = engine.core.ledger.flush_reports() {
+        eprintln!("{}", message);
+        process::exit(1);
     }
-    wtr.flush().unwrap();
 }
@@ -2,9 +2,10 @@ use rust_decimal::Decimal;
 
 use crate::{
     account::Account,
-    engine::EngineCore,
+    engine::{EngineCore, Outcome},
     event::{Event, EventType},
-    ledger::Transaction,
+    ledger::{Transaction, TransactionStatus},
+    lifecycle::AccountChange,
 };
 
 pub struct EventProcessor {
@@ -16,13 +17,40 @@ impl EventProcessor {
         EventProcessor { engine_core }
     }
 
-    pub fn process(&self, event: &Event) {
+    pub fn process(&self, event: &Event) -> Result<(), String> {
         match event.tx_type {
             EventType::Deposit => self.handle_deposit(event),
             EventType::Withdrawal => self.handle_withdrawal(event),
-            EventType::Dispute => self.handle_dispute(event),
-            EventType::Resolve => self.handle_resolve(event),
-            EventType::Chargeback => self.handle_chargeback(event),
+            EventType::Dispute
+            | EventType::Resolve
+            | EventType::Chargeback
+            | EventType::ChargebackReversal => {
+                let result = self.handle_dispute_event(event);
+                self.engine_core.ledger.release(event);
+                result
+            }
+            EventType::Close => self.handle_close(event),
+            EventType::Lock => self.handle_lock(event),
+        }
+    }
+
+    /// Pairs the result of an event with the client's account as it stands now.
+    pub fn outcome(&self, client: u16, result: Result<(), String>) -> Outcome {
+        Outcome {
+            result,
+            account: self.engine_core.chart.account(client),
+        }
+    }
+
+    /// The result of a repeated transaction is that of the original, which the client's worker
+    /// got to first.
+    pub fn repeated(&self, event: &Event) -> Result<(), String> {
+        match self.engine_core.ledger.status(event.tx, event.client) {
+            Some(TransactionStatus::Rejected(reason)) => Err(reason),
+            Some(TransactionStatus::Accepted) | None => {
+                Err(format!("Transaction {} hasn't been applied", event.tx))
+            }
+            Some(_) => Ok(()),
         }
     }
 
@@ -32,83 +60,149 @@ impl EventProcessor {
             .load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    fn handle_deposit(&self, event: &Event) {
-        self.with_account(event.client, |account| {
-            if let Some(amount) = self.get_transaction_amount(event) {
-                account.deposit(amount);
-            }
+    fn handle_deposit(&self, event: &Event) -> Result<(), String> {
+        let result = self.with_account(event.client, |account| {
+            let amount = self.get_transaction_amount(event)?;
+            account.deposit(amount);
+            Ok(())
         });
+        self.record_outcome(event, result)
     }
 
-    fn handle_withdrawal(&self, event: &Event) {
-        self.with_account(event.client, |account| {
-            if let Some(amount) = self.get_transaction_amount(event) {
-                if !account.locked() && account.available() >= amount {
-                    account.withdraw(amount);
-                }
+    fn handle_withdrawal(&self, event: &Event) -> Result<(), String> {
+        let result = self.with_account(event.client, |account| {
+            let amount = self.get_transaction_amount(event)?;
+            if account.locked() {
+                return Err(format!("Account {} is locked", event.client));
+            }
+            if account.available() < amount {
+                return Err(format!("Insufficient funds for transaction {}", event.tx));
             }
+            account.withdraw(amount);
+            Ok(())
         });
+        self.record_outcome(event, result)
     }
 
-    fn handle_dispute(&self, event: &Event) {
-        if let Some(amount) = self.get_dispute_amount(event) {
-            self.with_account(event.client, |account| {
-                account.hold(amount);
-            });
-        }
-    }
-
-    fn handle_resolve(&self, event: &Event) {
-        if let Some(amount) = self.get_dispute_amount(event) {
-            self.with_account(event.client, |account| {
-                account.resolve(amount);
-            });
+    /// Records the payout before sweeping the funds, so an account is never closed without one.
+    /// A rejected close keeps its payout's ID, as a rejected deposit or withdrawal does.
+    fn handle_close(&self, event: &Event) -> Result<(), String> {
+        let ledger = &self.engine_core.ledger;
+        let result = match self.engine_core.chart.account(event.client) {
+            None => Err(format!("Can't find an account for client {}", event.client)),
+            Some(_) => self.with_account(event.client, |account| {
+                let payout = account.payout()?;
+                ledger.record_payout(event.tx, event.client, payout)?;
+                account.close()?;
+                ledger.index_event(event.client, event.tx, EventType::Close, payout);
+                Ok(())
+            }),
+        };
+        if let Err(reason) = &result {
+            ledger.update_status(
+                event.tx,
+                event.client,
+                TransactionStatus::Rejected(reason.clone()),
+            );
         }
+        result
     }
 
-    fn handle_chargeback(&self, event: &Event) {
-        if let Some(amount) = self.get_dispute_amount(event) {
-            self.with_account(event.client, |account| {
-                account.reject(amount);
-            });
+    /// Locks the account as a chargeback would, and records the lock in the index so the
+    /// ledger's balances agree with the chart.
+    fn handle_lock(&self, event: &Event) -> Result<(), String> {
+        if self.engine_core.chart.account(event.client).is_none() {
+            return Err(format!("Can't find an account for client {}", event.client));
         }
+        self.with_account(event.client, |account| {
+            account.lock();
+            Ok(())
+        })?;
+        self.engine_core
+            .ledger
+            .index_event(event.client, event.tx, EventType::Lock, Decimal::ZERO);
+        Ok(())
     }
 
-    fn with_account<F>(&self, client_id: u16, action: F)
+    fn with_account<F>(&self, client_id: u16, mut action: F) -> Result<(), String>
     where
-        F: FnOnce(&mut Account),
+        F: FnMut(&mut Account) -> Result<(), String>,
     {
-        let mut account = self
-            .engine_core
-            .chart
-            .entry(client_id)
-            .or_insert_with(|| Account::new(client_id));
-        action(&mut account);
+        self.engine_core.chart.update(client_id, &mut |account| {
+            if account.closed() {
+                return Err(format!("Account {} is closed", client_id));
+            }
+            action(account)
+        })
     }
 
-    fn get_transaction_amount(&self, event: &Event) -> Option<Decimal> {
-        let tx = self
-            .engine_core
-            .ledger
-            .fetch_transaction(event.tx, event.client);
+    /// Applies a dispute event for the amount and transition the ledger records for it. The
+    /// ledger only records the event once the account is known to take it.
+    fn handle_dispute_event(&self, event: &Event) -> Result<(), String> {
+        let ledger = &self.engine_core.ledger;
+        let on_deposit = matches!(
+            ledger.fetch_transaction(event.tx, event.client),
+            Some(Transaction::Deposit { .. })
+        );
 
-        match tx {
-            Some(Transaction::Deposit { amount, .. })
-            | Some(Transaction::Withdrawal { amount, .. }) => Some(amount),
-            None => None,
+        let mut applied = None;
+        self.with_account(event.client, |account| {
+            let (transition, amount) = ledger.record_dispute(event)?;
+            let mut change = transition.clone();
+            if change.account == Some(AccountChange::Unlock)
+                && ledger.locked_by_others(event.client, event.tx)
+            {
+                // Another chargeback still stands.
+                change.account = None;
+            }
+            // Disputes on deposits are negative, on withdrawals positive.
+            account.apply(&change, if on_deposit { -amount } else { amount });
+            applied = Some((transition, amount));
+            Ok(())
+        })?;
+
+        if let Some((transition, amount)) = applied {
+            ledger.index_dispute(
+                event.client,
+                event.tx,
+                event.tx_type.clone(),
+                amount,
+                &transition,
+            );
         }
+        Ok(())
     }
 
-    fn get_dispute_amount(&self, event: &Event) -> Option<Decimal> {
+    fn record_outcome(&self, event: &Event, result: Result<(), String>) -> Result<(), String> {
+        let ledger = &self.engine_core.ledger;
+        match &result {
+            Ok(()) => {
+                let amount = self.get_transaction_amount(event)?;
+                ledger.update_status(event.tx, event.client, TransactionStatus::Applied);
+                ledger.index_event(event.client, event.tx, event.tx_type.clone(), amount);
+            }
+            Err(reason) => ledger.update_status(
+                event.tx,
+                event.client,
+                TransactionStatus::Rejected(reason.clone()),
+            ),
+        }
+        result
+    }
+
+    fn get_transaction_amount(&self, event: &Event) -> Result<Decimal, String> {
         let tx = self
             .engine_core
             .ledger
             .fetch_transaction(event.tx, event.client);
 
         match tx {
-            Some(Transaction::Deposit { amount, .. }) => Some(-amount),
-            Some(Transaction::Withdrawal { amount, .. }) => Some(amount),
-            _ => None,
+            Some(Transaction::Deposit { amount, .. })
+            | Some(Transaction::Withdrawal { amount, .. }) => Ok(amount),
+            _ => Err(format!(
+                "Can't find a transaction with ID {} for client {}",
+                event.tx, event.client
+            )),
         }
     }
 }
@@ -116,408 +210,711 @@ impl EventProcessor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{ledger::Ledger, lifecycle::DisputeProfile, verify};
     use rust_decimal::dec;
 
     #[test]
     fn test_adding_amount() {
         let engine_core = EngineCore::default();
-        let events = vec![Event {
-            tx_type: EventType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(10.0),
-        }];
+        let events = vec![Event::deposit(1, 1, 10.0)];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(10.0));
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(10.0)
+        );
     }
 
     #[test]
     fn test_adding_same_amount_twice() {
         let engine_core = EngineCore::default();
-        let events = vec![
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(10.0),
-            },
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 2,
-                amount: Some(20.0),
-            },
-        ];
+        let events = vec![Event::deposit(1, 1, 10.0), Event::deposit(1, 2, 20.0)];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(30.0));
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(30.0)
+        );
     }
 
     #[test]
     fn test_withdrawal_amount() {
         let engine_core = EngineCore::default();
-        let events = vec![Event {
-            tx_type: EventType::Withdrawal,
-            client: 1,
-            tx: 1,
-            amount: Some(10.0),
-        }];
+        let events = vec![Event::withdrawal(1, 1, 10.0)];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(0));
+        assert_eq!(engine_core.chart.account(1).unwrap().available(), dec!(0));
     }
 
     #[test]
     fn test_withdrawal_amount_after_adding() {
         let engine_core = EngineCore::default();
-        let events = vec![
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(20.0),
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 2,
-                amount: Some(5.0),
-            },
-        ];
+        let events = vec![Event::deposit(1, 1, 20.0), Event::withdrawal(1, 2, 5.0)];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(15.0));
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(15.0)
+        );
     }
 
     #[test]
     fn test_twice_withdrawal_amount_after_adding() {
         let engine_core = EngineCore::default();
         let events = vec![
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(20.0),
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 2,
-                amount: Some(5.0),
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 3,
-                amount: Some(5.0),
-            },
+            Event::deposit(1, 1, 20.0),
+            Event::withdrawal(1, 2, 5.0),
+            Event::withdrawal(1, 3, 5.0),
         ];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(10.0));
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(10.0)
+        );
     }
 
     #[test]
     fn test_dispute_on_deposit_is_not_ignored() {
         let engine_core = EngineCore::default();
         let events = vec![
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(30.0),
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 2,
-                amount: Some(20.0),
-            },
-            Event {
-                tx_type: EventType::Dispute,
-                client: 1,
-                tx: 1,
-                amount: None,
-            },
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 1),
         ];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(-20.0));
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(-20.0)
+        );
     }
 
     #[test]
     fn test_dispute_on_deposit_with_resolve() {
         let engine_core = EngineCore::default();
         let events = vec![
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(30.0),
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 2,
-                amount: Some(20.0),
-            },
-            Event {
-                tx_type: EventType::Dispute,
-                client: 1,
-                tx: 1,
-                amount: None,
-            },
-            Event {
-                tx_type: EventType::Resolve,
-                client: 1,
-                tx: 1,
-                amount: None,
-            },
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 1),
+            Event::resolve(1, 1),
         ];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(-20.0));
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(-20.0)
+        );
     }
 
     #[test]
     fn test_dispute_on_deposit_with_chargeback() {
         let engine_core = EngineCore::default();
         let events = vec![
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(30.0),
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 2,
-                amount: Some(20.0),
-            },
-            Event {
-                tx_type: EventType::Dispute,
-                client: 1,
-                tx: 1,
-                amount: None,
-            },
-            Event {
-                tx_type: EventType::Chargeback,
-                client: 1,
-                tx: 1,
-                amount: None,
-            },
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 1),
+            Event::chargeback(1, 1),
         ];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(10.0));
-        assert!(engine_core.chart.get(&1).unwrap().locked());
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(10.0)
+        );
+        assert!(engine_core.chart.account(1).unwrap().locked());
     }
 
     #[test]
     fn test_get_money_from_negative_balance() {
         let engine_core = EngineCore::default();
         let events = vec![
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(30.0),
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 2,
-                amount: Some(20.0),
-            },
-            Event {
-                tx_type: EventType::Dispute,
-                client: 1,
-                tx: 1,
-                amount: None,
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 3,
-                amount: Some(20.0),
-            },
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 1),
+            Event::withdrawal(1, 3, 20.0),
         ];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(-20.0));
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(-20.0)
+        );
     }
 
     #[test]
     fn test_dispute_on_withdrawal() {
         let engine_core = EngineCore::default();
         let events = vec![
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(30.0),
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 2,
-                amount: Some(20.0),
-            },
-            Event {
-                tx_type: EventType::Dispute,
-                client: 1,
-                tx: 2,
-                amount: None,
-            },
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 2),
         ];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(10.0));
-        assert_eq!(engine_core.chart.get(&1).unwrap().held(), dec!(20.0));
-        assert_eq!(engine_core.chart.get(&1).unwrap().total(), dec!(30.0));
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(10.0)
+        );
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(20.0));
+        assert_eq!(engine_core.chart.account(1).unwrap().total(), dec!(30.0));
     }
 
     #[test]
     fn test_dispute_on_withdrawal_with_resolve() {
         let engine_core = EngineCore::default();
         let events = vec![
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(30.0),
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 2,
-                amount: Some(20.0),
-            },
-            Event {
-                tx_type: EventType::Dispute,
-                client: 1,
-                tx: 2,
-                amount: None,
-            },
-            Event {
-                tx_type: EventType::Resolve,
-                client: 1,
-                tx: 2,
-                amount: None,
-            },
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 2),
+            Event::resolve(1, 2),
         ];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(30.0));
-        assert_eq!(engine_core.chart.get(&1).unwrap().held(), dec!(0.0));
-        assert_eq!(engine_core.chart.get(&1).unwrap().total(), dec!(30.0));
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(30.0)
+        );
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(0.0));
+        assert_eq!(engine_core.chart.account(1).unwrap().total(), dec!(30.0));
     }
 
     #[test]
     fn test_dispute_on_withdrawal_with_chargeback() {
         let engine_core = EngineCore::default();
         let events = vec![
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(30.0),
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 2,
-                amount: Some(20.0),
-            },
-            Event {
-                tx_type: EventType::Dispute,
-                client: 1,
-                tx: 2,
-                amount: None,
-            },
-            Event {
-                tx_type: EventType::Chargeback,
-                client: 1,
-                tx: 2,
-                amount: None,
-            },
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 2),
+            Event::chargeback(1, 2),
         ];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(10.0));
-        assert_eq!(engine_core.chart.get(&1).unwrap().held(), dec!(0.0));
-        assert_eq!(engine_core.chart.get(&1).unwrap().total(), dec!(10.0));
-        assert!(engine_core.chart.get(&1).unwrap().locked());
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(10.0)
+        );
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(0.0));
+        assert_eq!(engine_core.chart.account(1).unwrap().total(), dec!(10.0));
+        assert!(engine_core.chart.account(1).unwrap().locked());
     }
 
     #[test]
     fn test_dispute_on_withdrawal_with_chargeback_disables_withdrawal() {
         let engine_core = EngineCore::default();
         let events = vec![
-            Event {
-                tx_type: EventType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(30.0),
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 2,
-                amount: Some(20.0),
-            },
-            Event {
-                tx_type: EventType::Dispute,
-                client: 1,
-                tx: 2,
-                amount: None,
-            },
-            Event {
-                tx_type: EventType::Chargeback,
-                client: 1,
-                tx: 2,
-                amount: None,
-            },
-            Event {
-                tx_type: EventType::Withdrawal,
-                client: 1,
-                tx: 3,
-                amount: Some(10.0),
-            },
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 2),
+            Event::chargeback(1, 2),
+            Event::withdrawal(1, 3, 10.0),
+        ];
+
+        process_events(engine_core.clone(), events);
+
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(10.0)
+        );
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(0.0));
+        assert_eq!(engine_core.chart.account(1).unwrap().total(), dec!(10.0));
+        assert!(engine_core.chart.account(1).unwrap().locked());
+    }
+
+    #[test]
+    fn test_transaction_statuses() {
+        let engine_core = EngineCore::default();
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 50.0),
+            Event::deposit(1, 3, 10.0),
+            Event::dispute(1, 3),
+            Event::chargeback(1, 3),
+        ];
+
+        process_events(engine_core.clone(), events);
+
+        let ledger = &engine_core.ledger;
+        assert_eq!(ledger.status(1, 1), Some(TransactionStatus::Applied));
+        assert_eq!(
+            ledger.status(2, 1),
+            Some(TransactionStatus::Rejected(String::from(
+                "Insufficient funds for transaction 2"
+            )))
+        );
+        assert_eq!(ledger.status(3, 1), Some(TransactionStatus::ChargedBack));
+    }
+
+    #[test]
+    fn test_dispute_on_rejected_withdrawal_is_refused() {
+        let engine_core = EngineCore::default();
+        let events = vec![
+            Event::deposit(1, 1, 10.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 2),
+        ];
+
+        process_events(engine_core.clone(), events);
+
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(10.0)
+        );
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(0));
+        assert!(matches!(
+            engine_core.ledger.status(2, 1),
+            Some(TransactionStatus::Rejected(_))
+        ));
+    }
+
+    #[test]
+    fn test_partial_disputes_on_deposit() {
+        let engine_core = EngineCore::default();
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::dispute(1, 1).with_amount(10.0),
+            Event::dispute(1, 1).with_amount(5.0),
+            Event::resolve(1, 1).with_amount(10.0),
+        ];
+
+        process_events(engine_core.clone(), events);
+
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(15.0)
+        );
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(5.0));
+        assert_eq!(
+            engine_core.ledger.status(1, 1),
+            Some(TransactionStatus::Disputed)
+        );
+
+        process_events(engine_core.clone(), vec![Event::chargeback(1, 1)]);
+
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(20.0)
+        );
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(0));
+        assert!(engine_core.chart.account(1).unwrap().locked());
+        assert_eq!(
+            engine_core.ledger.status(1, 1),
+            Some(TransactionStatus::ChargedBack)
+        );
+    }
+
+    #[test]
+    fn test_redispute_after_resolve_on_withdrawal() {
+        let engine_core = EngineCore {
+            ledger: Ledger::new(DisputeProfile::default().with_reopen_limit(1).unwrap()),
+            ..Default::default()
+        };
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 2),
+            Event::resolve(1, 2),
+            Event::dispute(1, 2),
+        ];
+
+        process_events(engine_core.clone(), events);
+
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(30.0)
+        );
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(20.0));
+        assert_eq!(
+            engine_core.ledger.status(2, 1),
+            Some(TransactionStatus::Disputed)
+        );
+    }
+
+    #[test]
+    fn test_chargeback_reversal_on_deposit() {
+        let engine_core = EngineCore::default();
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 1),
+            Event::chargeback(1, 1),
+            Event::chargeback_reversal(1, 1),
+        ];
+
+        process_events(engine_core.clone(), events);
+
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(-20.0)
+        );
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(0));
+        assert!(!engine_core.chart.account(1).unwrap().locked());
+        assert_eq!(
+            engine_core.ledger.status(1, 1),
+            Some(TransactionStatus::ChargebackReversed)
+        );
+    }
+
+    #[test]
+    fn test_chargeback_reversal_on_withdrawal() {
+        let engine_core = EngineCore::default();
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 20.0),
+            Event::dispute(1, 2),
+            Event::chargeback(1, 2),
+            Event::chargeback_reversal(1, 2),
         ];
 
         process_events(engine_core.clone(), events);
 
-        assert_eq!(engine_core.chart.get(&1).unwrap().available(), dec!(10.0));
-        assert_eq!(engine_core.chart.get(&1).unwrap().held(), dec!(0.0));
-        assert_eq!(engine_core.chart.get(&1).unwrap().total(), dec!(10.0));
-        assert!(engine_core.chart.get(&1).unwrap().locked());
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(30.0)
+        );
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(0));
+        assert!(!engine_core.chart.account(1).unwrap().locked());
+        assert_eq!(
+            engine_core.ledger.status(2, 1),
+            Some(TransactionStatus::ChargebackReversed)
+        );
+    }
+
+    #[test]
+    fn test_reversal_keeps_lock_of_other_chargebacks() {
+        let engine_core = EngineCore::default();
+        let events = vec![
+            Event::deposit(1, 1, 10.0),
+            Event::deposit(1, 2, 5.0),
+            Event::dispute(1, 1),
+            Event::chargeback(1, 1),
+            Event::dispute(1, 2),
+            Event::chargeback(1, 2),
+            Event::chargeback_reversal(1, 1),
+        ];
+        process_events(engine_core.clone(), events);
+
+        assert!(engine_core.chart.account(1).unwrap().locked());
+        assert!(engine_core.ledger.rollup(1).locked);
+
+        process_events(engine_core.clone(), vec![Event::chargeback_reversal(1, 2)]);
+        assert!(!engine_core.chart.account(1).unwrap().locked());
+        assert!(verify::reconcile(&engine_core.ledger, engine_core.chart.as_ref()).is_empty());
+    }
+
+    #[test]
+    fn test_reversal_keeps_operator_lock() {
+        let engine_core = EngineCore::default();
+        let events = vec![
+            Event::deposit(1, 1, 10.0),
+            Event::dispute(1, 1),
+            Event::chargeback(1, 1),
+            Event::lock(1),
+            Event::chargeback_reversal(1, 1),
+        ];
+        process_events(engine_core.clone(), events);
+
+        assert!(engine_core.chart.account(1).unwrap().locked());
+        assert!(engine_core.ledger.rollup(1).locked);
+        assert!(verify::reconcile(&engine_core.ledger, engine_core.chart.as_ref()).is_empty());
+    }
+
+    #[test]
+    fn test_custom_dispute_profile() {
+        let profile = DisputeProfile::parse(
+            "from, event, to, effect, account, limit
+            undisputed, dispute, retrieval, none, ,
+            retrieval, dispute, disputed, hold, ,
+            disputed, chargeback, charged_back, reject, ,"
+                .as_bytes(),
+        )
+        .unwrap();
+        let engine_core = EngineCore {
+            ledger: Ledger::new(profile),
+            ..Default::default()
+        };
+        let dispute = Event::dispute(1, 1);
+        let events = vec![Event::deposit(1, 1, 30.0), dispute.clone()];
+
+        process_events(engine_core.clone(), events);
+
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(0));
+        assert_eq!(
+            engine_core.ledger.dispute_state(1, 1),
+            Some(String::from("retrieval"))
+        );
+
+        process_events(engine_core.clone(), vec![dispute, Event::chargeback(1, 1)]);
+
+        assert_eq!(
+            engine_core.chart.account(1).unwrap().available(),
+            dec!(30.0)
+        );
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(0));
+        assert!(!engine_core.chart.account(1).unwrap().locked());
+        assert_eq!(
+            engine_core.ledger.status(1, 1),
+            Some(TransactionStatus::ChargedBack)
+        );
+    }
+
+    #[test]
+    fn test_close_sweeps_available_funds() {
+        let engine_core = EngineCore::default();
+        let events = vec![Event::deposit(1, 1, 30.0), Event::close(1, 2)];
+
+        process_events(engine_core.clone(), events);
+
+        assert_eq!(engine_core.chart.account(1).unwrap().available(), dec!(0));
+        assert!(engine_core.chart.account(1).unwrap().closed());
+        assert_eq!(
+            engine_core.ledger.fetch_transaction(2, 1),
+            Some(Transaction::Payout {
+                amount: dec!(30.0),
+                client: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_close_with_open_dispute_is_rejected() {
+        let engine_core = EngineCore::default();
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::dispute(1, 1),
+            Event::close(1, 2),
+        ];
+
+        process_events(engine_core.clone(), events);
+
+        assert!(!engine_core.chart.account(1).unwrap().closed());
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(30.0));
+        assert!(matches!(
+            engine_core.ledger.status(2, 1),
+            Some(TransactionStatus::Rejected(_))
+        ));
+    }
+
+    #[test]
+    fn test_events_after_close_are_rejected() {
+        let engine_core = EngineCore::default();
+        let processor = EventProcessor::new(engine_core.clone());
+        let close = Event::close(1, 1);
+        let deposit = Event::deposit(1, 2, 10.0);
+
+        process_events(engine_core.clone(), vec![Event::deposit(1, 3, 5.0)]);
+        assert!(engine_core.ledger.add_event(close.clone()).is_ok());
+        assert!(processor.process(&close).is_ok());
+        assert!(engine_core.ledger.add_event(deposit.clone()).is_ok());
+        assert_eq!(
+            processor.process(&deposit).unwrap_err(),
+            "Account 1 is closed"
+        );
+
+        assert_eq!(engine_core.chart.account(1).unwrap().available(), dec!(0));
+    }
+
+    #[test]
+    fn test_refused_disputes_are_not_recorded() {
+        let engine_core = EngineCore::default();
+        let events = vec![
+            Event::deposit(1, 1, 10.0),
+            Event::withdrawal(1, 2, 50.0),
+            Event::dispute(1, 2),
+            Event::deposit(2, 3, 5.0),
+            Event::close(2, 4),
+            Event::dispute(2, 3),
+        ];
+
+        // Every event is queued before the first one is applied.
+        process_events(engine_core.clone(), events);
+
+        let ledger = &engine_core.ledger;
+        assert!(ledger.history(2, None).unwrap().disputes.is_empty());
+        assert!(ledger.history(3, None).unwrap().disputes.is_empty());
+        assert!(ledger.disputed().is_empty());
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(0));
+    }
+
+    #[test]
+    fn test_close_keeps_funds_when_rejected() {
+        let engine_core = EngineCore::default();
+        let processor = EventProcessor::new(engine_core.clone());
+        let events = vec![
+            Event::deposit(1, 1, 10.0),
+            Event::deposit(2, 2, 10.0),
+            Event::dispute(2, 2),
+            Event::chargeback(2, 2),
+            Event::deposit(2, 3, 5.0),
+        ];
+        for event in events {
+            let event = engine_core.ledger.add_event(event).unwrap().unwrap();
+            processor.process(&event).unwrap();
+        }
+
+        let close = engine_core.ledger.add_event(Event::close(2, 4)).unwrap();
+        assert_eq!(
+            processor.process(&close.unwrap()),
+            Err(String::from("Account 2 is locked"))
+        );
+        assert_eq!(engine_core.chart.account(2).unwrap().available(), dec!(15));
+
+        assert_eq!(
+            engine_core.ledger.status(4, 2),
+            Some(TransactionStatus::Rejected(String::from(
+                "Account 2 is locked"
+            )))
+        );
+
+        // The payout's ID is claimed as soon as the close is accepted.
+        let close = engine_core.ledger.add_event(Event::close(1, 5)).unwrap();
+        assert_eq!(
+            engine_core.ledger.add_event(Event::deposit(1, 5, 1.0)),
+            Err(String::from("Transaction with ID 5 already exists"))
+        );
+        assert_eq!(processor.process(&close.unwrap()), Ok(()));
+        let account = engine_core.chart.account(1).unwrap();
+        assert_eq!(account.available(), dec!(0));
+        assert!(account.closed());
+        assert_eq!(
+            engine_core.ledger.fetch_transaction(5, 1),
+            Some(Transaction::Payout {
+                amount: dec!(10),
+                client: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_close_unknown_client_is_rejected() {
+        let engine_core = EngineCore::default();
+        let processor = EventProcessor::new(engine_core.clone());
+
+        let close = engine_core.ledger.add_event(Event::close(5, 1)).unwrap();
+        assert_eq!(
+            processor.process(&close.unwrap()),
+            Err(String::from("Can't find an account for client 5"))
+        );
+        assert!(engine_core.chart.accounts().is_empty());
+    }
+
+    #[test]
+    fn test_balance_at_transaction() {
+        let engine_core = EngineCore {
+            ledger: Ledger::default().with_dispute_window(3),
+            ..EngineCore::default()
+        };
+        let events = vec![
+            Event::deposit(1, 1, 10.0),
+            Event::withdrawal(1, 2, 4.0),
+            Event::deposit(1, 3, 5.0),
+            Event::dispute(1, 3),
+            Event::chargeback(1, 3),
+            Event::deposit(1, 4, 1.0),
+            Event::deposit(1, 5, 2.0),
+        ];
+        let processor = EventProcessor::new(engine_core.clone());
+        for event in events {
+            let event = engine_core.ledger.add_event(event).unwrap().unwrap();
+            processor.process(&event).unwrap();
+        }
+
+        let balance = |tx| {
+            engine_core
+                .ledger
+                .balance_at(1, tx)
+                .map(|b| (b.available, b.held, b.total, b.locked))
+        };
+        // Transactions 1 and 2 have been evicted, later balances build on what they carried.
+        assert_eq!(
+            balance(1),
+            Err(String::from("No applied transaction 1 for client 1"))
+        );
+        assert_eq!(balance(3), Ok((dec!(11), dec!(0), dec!(11), false)));
+        assert_eq!(balance(4), Ok((dec!(12), dec!(0), dec!(12), true)));
+        assert_eq!(balance(5), Ok((dec!(14), dec!(0), dec!(14), true)));
+        assert_eq!(engine_core.chart.account(1).unwrap().available(), dec!(14));
+    }
+
+    #[test]
+    fn test_balance_at_after_latest_event_evicted() {
+        let engine_core = EngineCore {
+            ledger: Ledger::default().with_dispute_window(2),
+            ..EngineCore::default()
+        };
+        let events = vec![
+            Event::deposit(1, 2, 5.0),
+            Event::dispute(1, 2),
+            Event::deposit(1, 1, 10.0),
+            Event::deposit(2, 4, 1.0),
+            Event::deposit(2, 5, 1.0),
+            Event::deposit(2, 6, 1.0),
+            Event::deposit(1, 3, 3.0),
+        ];
+        let processor = EventProcessor::new(engine_core.clone());
+        for event in events {
+            let event = engine_core.ledger.add_event(event).unwrap().unwrap();
+            processor.process(&event).unwrap();
+        }
+
+        // Transaction 1 was the client's latest event when it was evicted.
+        assert_eq!(engine_core.ledger.fetch_transaction(1, 1), None);
+        let balance = engine_core.ledger.balance_at(1, 3).unwrap();
+        assert_eq!((balance.available, balance.total), (dec!(13), dec!(18)));
+        let account = engine_core.chart.account(1).unwrap();
+        assert_eq!((account.available(), account.total()), (dec!(13), dec!(18)));
+    }
+
+    #[test]
+    fn test_queued_dispute_keeps_transaction() {
+        let engine_core = EngineCore {
+            ledger: Ledger::default().with_dispute_window(2),
+            ..EngineCore::default()
+        };
+        let ledger = &engine_core.ledger;
+        let processor = EventProcessor::new(engine_core.clone());
+        let deposit = ledger
+            .add_event(Event::deposit(1, 1, 10.0))
+            .unwrap()
+            .unwrap();
+        processor.process(&deposit).unwrap();
+        // Client 2's worker gets ahead of the dispute still queued for client 1.
+        let dispute = ledger.add_event(Event::dispute(1, 1)).unwrap().unwrap();
+        for tx in 2..6 {
+            let event = ledger
+                .add_event(Event::deposit(2, tx, 1.0))
+                .unwrap()
+                .unwrap();
+            processor.process(&event).unwrap();
+        }
+
+        assert_eq!(processor.process(&dispute), Ok(()));
+        assert_eq!(engine_core.chart.account(1).unwrap().held(), dec!(10));
     }
 
     fn process_events(engine_core: EngineCore, events: Vec<Event>) {
         let processor = EventProcessor {
             engine_core: engine_core.clone(),
         };
-        events
-            .iter()
-            .for_each(|event| engine_core.ledger.add_event(event.clone()).unwrap());
+        let events: Vec<Event> = events
+            .into_iter()
+            .filter_map(|event| engine_core.ledger.add_event(event).unwrap())
+            .collect();
 
         for event in events {
-            processor.process(&event);
+            let _ = processor.process(&event);
         }
     }
 }
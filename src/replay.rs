@@ -0,0 +1,131 @@
+use csv::{ReaderBuilder, StringRecord, Trim};
+use std::{
+    fs::File,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use crate::{
+    engine::EngineCore, event::Event, ledger::Ledger, processor::EventProcessor,
+    store::AccountStore,
+};
+
+/// Where a replay stops.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Until {
+    /// After the row that introduced the transaction.
+    Tx(u32),
+    /// After the last row that starts on or before the line; the header is line 1.
+    Line(u64),
+}
+
+/// Rebuilds the state as of a point in an event log by applying its rows one at a time, in
+/// order, so the result doesn't depend on how the workers would have interleaved clients.
+pub fn replay(
+    path: &str,
+    until: Until,
+    ledger: Ledger,
+    chart: Arc<dyn AccountStore>,
+) -> Result<EngineCore, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to read from {}: {}", path, e))?;
+    let core = EngineCore {
+        ledger,
+        chart,
+        shutdown: Arc::new(AtomicBool::new(false)),
+    };
+    let processor = EventProcessor::new(core.clone());
+
+    let mut rdr = ReaderBuilder::new().trim(Trim::All).from_reader(file);
+    let headers = rdr
+        .headers()
+        .map_err(|e| format!("Can't read the header of {}: {}", path, e))?
+        .clone();
+    let mut record = StringRecord::new();
+    loop {
+        match rdr.read_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                eprintln!("Can't parse: {:?}", e);
+                continue;
+            }
+        }
+        let line = record.position().map_or(0, |position| position.line());
+        if matches!(until, Until::Line(last) if line > last) {
+            return Ok(core);
+        }
+        let event: Event = match record.deserialize(Some(&headers)) {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("Can't parse: {:?}", e);
+                continue;
+            }
+        };
+
+        let tx = event.tx;
+        match core.ledger.add_event(event) {
+            Ok(Some(event)) => {
+                if let Err(message) = processor.process(&event) {
+                    eprintln!("{}", message);
+                }
+            }
+            Ok(None) => {}
+            Err(message) => eprintln!("{}", message),
+        }
+        if until == Until::Tx(tx) {
+            return Ok(core);
+        }
+    }
+
+    match until {
+        Until::Tx(tx) => Err(format!("Can't find transaction {} in {}", tx, path)),
+        Until::Line(_) => Ok(core),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryChart;
+    use rust_decimal::{dec, Decimal};
+
+    fn balances(until: Until) -> Result<Vec<(Decimal, Decimal, bool)>, String> {
+        let core = replay(
+            "fixtures/replay.csv",
+            until,
+            Ledger::default(),
+            Arc::new(MemoryChart::default()),
+        )?;
+        Ok(core
+            .chart
+            .accounts()
+            .iter()
+            .map(|account| (account.available(), account.held(), account.locked()))
+            .collect())
+    }
+
+    #[test]
+    fn test_replay_until_tx() {
+        assert_eq!(
+            balances(Until::Tx(2)),
+            Ok(vec![(dec!(10), dec!(0), false), (dec!(5), dec!(0), false)])
+        );
+        assert_eq!(
+            balances(Until::Tx(3)),
+            Ok(vec![(dec!(6), dec!(0), false), (dec!(5), dec!(0), false)])
+        );
+        assert!(balances(Until::Tx(9)).is_err());
+    }
+
+    #[test]
+    fn test_replay_until_line() {
+        assert_eq!(
+            balances(Until::Line(5)),
+            Ok(vec![(dec!(6), dec!(0), false), (dec!(0), dec!(5), false)])
+        );
+        assert_eq!(
+            balances(Until::Line(100)),
+            Ok(vec![(dec!(6), dec!(0), false), (dec!(5), dec!(0), true)])
+        );
+        assert_eq!(balances(Until::Line(1)), Ok(vec![]));
+    }
+}
@@ -44,6 +44,10 @@ mod tests {
                 client,
                 tx,
                 amount,
+                reason: None,
+                case_id: None,
+                notes: None,
+                sequence: 0,
             }
         }
 
@@ -66,6 +70,32 @@ mod tests {
         pub fn chargeback(client: u16, tx: u32) -> Self {
             Self::new(EventType::Chargeback, client, tx, None)
         }
+
+        pub fn chargeback_reversal(client: u16, tx: u32) -> Self {
+            Self::new(EventType::ChargebackReversal, client, tx, None)
+        }
+
+        pub fn close(client: u16, tx: u32) -> Self {
+            Self::new(EventType::Close, client, tx, None)
+        }
+
+        pub fn lock(client: u16) -> Self {
+            Self::new(EventType::Lock, client, 0, None)
+        }
+
+        pub fn with_amount(self, amount: f64) -> Self {
+            Event {
+                amount: Some(amount),
+                ..self
+            }
+        }
+
+        pub fn with_reason(self, reason: &str) -> Self {
+            Event {
+                reason: Some(reason.to_string()),
+                ..self
+            }
+        }
     }
 
     #[test]
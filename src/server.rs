@@ -0,0 +1,365 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    account::Account,
+    engine::{Engine, Outcome},
+    event::Event,
+    listener::POLL_INTERVAL,
+};
+
+/// What became of one submitted event.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Submission {
+    pub client: u16,
+    pub tx: u32,
+    pub accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The account right after the event, when the caller waited for it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<Account>,
+}
+
+impl Submission {
+    fn new(event: &Event, result: Result<(), String>) -> Self {
+        Submission {
+            client: event.client,
+            tx: event.tx,
+            accepted: result.is_ok(),
+            error: result.err(),
+            account: None,
+        }
+    }
+}
+
+/// Serves the engine over HTTP until `stop` is set, answering every request on its own thread so a
+/// `?wait` doesn't hold up the others. Events are submitted exactly as they are when read from a
+/// file, so they're processed asynchronously by the workers.
+pub fn serve(addr: &str, engine: &Engine, stop: &AtomicBool) -> Result<(), String> {
+    let server = Server::http(addr).map_err(|e| format!("Can't listen on {}: {}", addr, e))?;
+    eprintln!("Listening on {}", addr);
+    accept(server, engine, stop)
+}
+
+/// Waits for the requests in flight once `stop` is set, so the caller can drain the engine.
+fn accept(server: Server, engine: &Engine, stop: &AtomicBool) -> Result<(), String> {
+    let content_type = Header::from_bytes("Content-Type", "application/json")
+        .map_err(|_| String::from("Invalid content type header"))?;
+
+    thread::scope(|scope| {
+        while !stop.load(Ordering::Relaxed) {
+            match server.recv_timeout(POLL_INTERVAL) {
+                Ok(Some(request)) => {
+                    let content_type = content_type.clone();
+                    scope.spawn(move || handle(request, engine, content_type));
+                }
+                Ok(None) => {}
+                Err(e) => return Err(format!("Can't accept a request: {}", e)),
+            }
+        }
+        Ok(())
+    })
+}
+
+fn handle(mut request: Request, engine: &Engine, content_type: Header) {
+    let mut body = String::new();
+    let (status, value) = match request.as_reader().read_to_string(&mut body) {
+        Ok(_) => route(engine, request.method(), request.url(), &body),
+        Err(e) => error(400, format!("Can't read the request: {}", e)),
+    };
+    let response = Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    if let Err(e) = request.respond(response) {
+        eprintln!("Can't respond: {}", e);
+    }
+}
+
+fn route(engine: &Engine, method: &Method, url: &str, body: &str) -> (u16, Value) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (method, segments.as_slice()) {
+        (Method::Get, ["health"]) => (200, json!({ "status": "ok" })),
+        (Method::Post, ["events"]) => submit(engine, body, has_param(query, "wait")),
+        (Method::Get, ["accounts", client]) => account(engine, client, query),
+        (Method::Get, ["transactions", tx]) => transaction(engine, tx, query),
+        _ => error(404, format!("No route for {} {}", method, path)),
+    }
+}
+
+/// Takes a single event or a batch of events as a JSON array. With `wait` every event's outcome
+/// is awaited, so the answer tells whether it was applied and shows the account afterwards.
+fn submit(engine: &Engine, body: &str, wait: bool) -> (u16, Value) {
+    if body.trim_start().starts_with('[') {
+        let events: Vec<Event> = match serde_json::from_str(body) {
+            Ok(events) => events,
+            Err(e) => return error(400, format!("Invalid events: {}", e)),
+        };
+        let submissions: Vec<Submission> = events
+            .into_iter()
+            .map(|event| submission(engine, event, wait))
+            .collect();
+        return (200, json!(submissions));
+    }
+
+    let event: Event = match serde_json::from_str(body) {
+        Ok(event) => event,
+        Err(e) => return error(400, format!("Invalid event: {}", e)),
+    };
+    let submission = submission(engine, event, wait);
+    let status = match (submission.accepted, wait) {
+        (true, true) => 200,
+        (true, false) => 202,
+        (false, _) => 422,
+    };
+    (status, json!(submission))
+}
+
+fn submission(engine: &Engine, event: Event, wait: bool) -> Submission {
+    if !wait {
+        return Submission::new(&event, engine.submit_event(event.clone()));
+    }
+    let outcome = match engine.submit_tracked(event.clone()) {
+        Ok(completion) => completion.wait(),
+        Err(message) => Outcome::rejected(&message),
+    };
+    Submission {
+        account: outcome.account,
+        ..Submission::new(&event, outcome.result)
+    }
+}
+
+/// Whether the query sets `name` or `name=true`.
+fn has_param(query: &str, name: &str) -> bool {
+    query
+        .split('&')
+        .any(|pair| pair == name || pair.strip_prefix(name) == Some("=true"))
+}
+
+/// The current account, or with `?at=` the account right after that transaction.
+fn account(engine: &Engine, client: &str, query: &str) -> (u16, Value) {
+    let Ok(client) = client.parse::<u16>() else {
+        return error(400, format!("Invalid client {}", client));
+    };
+    if let Some(tx) = query.split('&').find_map(|pair| pair.strip_prefix("at=")) {
+        let Ok(tx) = tx.parse::<u32>() else {
+            return error(400, format!("Invalid transaction {}", tx));
+        };
+        return match engine.balance_at(client, tx) {
+            Ok(balance) => (200, json!(balance)),
+            Err(message) => error(404, message),
+        };
+    }
+    match engine.core.chart.account(client) {
+        Some(account) => (200, json!(account)),
+        None => error(404, format!("Can't find an account for client {}", client)),
+    }
+}
+
+/// Looks the transaction up by ID, or by ID and `?client=` when IDs are scoped per client.
+fn transaction(engine: &Engine, tx: &str, query: &str) -> (u16, Value) {
+    let Ok(tx) = tx.parse::<u32>() else {
+        return error(400, format!("Invalid transaction {}", tx));
+    };
+    let client = match query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("client="))
+    {
+        Some(client) => match client.parse::<u16>() {
+            Ok(client) => Some(client),
+            Err(_) => return error(400, format!("Invalid client {}", client)),
+        },
+        None => None,
+    };
+
+    match engine.core.ledger.history(tx, client) {
+        Some(history) => (
+            200,
+            json!({
+                "tx": history.id,
+                "client": history.transaction.client(),
+                "type": history.transaction.kind(),
+                "amount": history.transaction.amount().to_string(),
+                "status": history.status.to_string(),
+                "disputed": history.disputed.to_string(),
+                "disputes": history.disputes,
+            }),
+        ),
+        None => error(404, format!("Can't find a transaction with ID {}", tx)),
+    }
+}
+
+fn error(status: u16, message: String) -> (u16, Value) {
+    (status, json!({ "error": message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::Ledger;
+    use crate::store::MemoryChart;
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        sync::Arc,
+    };
+
+    fn engine() -> (Engine, Vec<std::thread::JoinHandle<()>>) {
+        let mut engine = Engine::new(Ledger::default(), Arc::new(MemoryChart::default()));
+        let handles = engine.start_workers();
+        (engine, handles)
+    }
+
+    #[test]
+    fn test_submit_events() {
+        let (engine, handles) = engine();
+
+        let (status, body) = route(
+            &engine,
+            &Method::Post,
+            "/events",
+            r#"{"type": "deposit", "client": 1, "tx": 1, "amount": 10.0}"#,
+        );
+        assert_eq!(status, 202);
+        assert_eq!(body, json!({ "client": 1, "tx": 1, "accepted": true }));
+
+        let (status, body) = route(
+            &engine,
+            &Method::Post,
+            "/events",
+            r#"[
+                {"type": "deposit", "client": 1, "tx": 2, "amount": 5.0},
+                {"type": "dispute", "client": 1, "tx": 7}
+            ]"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body[0]["accepted"], json!(true));
+        assert_eq!(body[1]["accepted"], json!(false));
+        assert_eq!(
+            body[1]["error"],
+            json!("Can't find a transaction with ID 7 for client 1")
+        );
+
+        let (status, _) = route(&engine, &Method::Post, "/events", r#"{"type": "refund"}"#);
+        assert_eq!(status, 400);
+
+        let (status, body) = route(
+            &engine,
+            &Method::Post,
+            "/events?wait",
+            r#"{"type": "withdrawal", "client": 1, "tx": 3, "amount": 4.0}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["account"]["available"], json!("11"));
+
+        let (status, body) = route(
+            &engine,
+            &Method::Post,
+            "/events?wait=true",
+            r#"{"type": "withdrawal", "client": 1, "tx": 4, "amount": 40.0}"#,
+        );
+        assert_eq!(status, 422);
+        assert_eq!(body["error"], json!("Insufficient funds for transaction 4"));
+        assert_eq!(body["account"]["available"], json!("11"));
+
+        // A repeated transaction is a no-op, but still reports the account.
+        let (status, body) = route(
+            &engine,
+            &Method::Post,
+            "/events?wait",
+            r#"{"type": "withdrawal", "client": 1, "tx": 3, "amount": 4.0}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["account"]["available"], json!("11"));
+        let (status, body) = route(
+            &engine,
+            &Method::Post,
+            "/events?wait",
+            r#"{"type": "withdrawal", "client": 1, "tx": 4, "amount": 40.0}"#,
+        );
+        assert_eq!(status, 422);
+        assert_eq!(body["error"], json!("Insufficient funds for transaction 4"));
+
+        engine.shutdown();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_query_state() {
+        let (engine, handles) = engine();
+        for event in [
+            Event::deposit(1, 1, 10.0),
+            Event::dispute(1, 1).with_reason("fraud"),
+        ] {
+            engine.submit_event(event).unwrap();
+        }
+        engine.shutdown();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let (status, body) = route(&engine, &Method::Get, "/accounts/1", "");
+        assert_eq!(status, 200);
+        assert_eq!(body["held"], json!("10"));
+
+        let (status, body) = route(&engine, &Method::Get, "/accounts/1?at=1", "");
+        assert_eq!(status, 200);
+        assert_eq!(body["available"], json!("10"));
+        assert_eq!(body["held"], json!("0"));
+        assert_eq!(route(&engine, &Method::Get, "/accounts/1?at=5", "").0, 404);
+
+        let (status, body) = route(&engine, &Method::Get, "/transactions/1?client=1", "");
+        assert_eq!(status, 200);
+        assert_eq!(body["status"], json!("disputed"));
+        assert_eq!(body["disputes"][0]["event"], json!("dispute"));
+        assert_eq!(body["disputes"][0]["details"]["reason"], json!("fraud"));
+
+        assert_eq!(route(&engine, &Method::Get, "/accounts/2", "").0, 404);
+        assert_eq!(route(&engine, &Method::Get, "/transactions/x", "").0, 400);
+        assert_eq!(route(&engine, &Method::Get, "/health", "").0, 200);
+        assert_eq!(route(&engine, &Method::Delete, "/accounts/1", "").0, 404);
+    }
+
+    #[test]
+    fn test_slow_request_does_not_block_others() {
+        let (engine, handles) = engine();
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let stop = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            scope.spawn(|| accept(server, &engine, &stop).unwrap());
+
+            // Its body never arrives, so this request is read until the client goes away.
+            let mut slow = TcpStream::connect(addr).unwrap();
+            slow.write_all(b"POST /events?wait HTTP/1.1\r\nContent-Length: 100\r\n\r\n{")
+                .unwrap();
+            thread::sleep(POLL_INTERVAL);
+
+            let mut health = TcpStream::connect(addr).unwrap();
+            health
+                .write_all(b"GET /health HTTP/1.1\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let mut reply = String::new();
+            health.read_to_string(&mut reply).unwrap();
+            assert!(reply.starts_with("HTTP/1.1 200"));
+
+            drop(slow);
+            stop.store(true, Ordering::Relaxed);
+        });
+
+        engine.shutdown();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    account::Account,
+    event::EventType,
+    ledger::{Ledger, Transaction},
+};
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct StatementLine {
+    #[serde(rename = "type")]
+    pub entry: String,
+    pub tx: Option<u32>,
+    pub amount: Option<Decimal>,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+}
+
+impl StatementLine {
+    fn new(entry: &str, tx: Option<u32>, amount: Option<Decimal>, account: &Account) -> Self {
+        StatementLine {
+            entry: entry.to_string(),
+            tx,
+            amount,
+            available: account.available(),
+            held: account.held(),
+            total: account.total(),
+        }
+    }
+}
+
+/// Replays the client's applied events from the ledger index, opening with the balances carried
+/// forward from evicted transactions.
+pub fn build(ledger: &Ledger, client: u16) -> Result<Vec<StatementLine>, String> {
+    let entries = ledger.client_entries(client);
+    let carried = ledger.carried(client);
+    if entries.is_empty() && carried.is_none() {
+        return Err(format!("No applied transactions for client {}", client));
+    }
+
+    let mut account = Account::new(client);
+    if let Some(carried) = carried {
+        account.deposit(carried.available);
+        if carried.closed {
+            account.close()?;
+        }
+        if carried.locked {
+            account.lock();
+        }
+    }
+    let mut lines = vec![StatementLine::new("opening", None, None, &account)];
+
+    for entry in entries {
+        let amount = entry.amount;
+        let signed = match ledger.fetch_transaction(entry.tx, client) {
+            Some(Transaction::Deposit { .. }) => -amount,
+            _ => amount,
+        };
+
+        let name = match (&entry.event, &entry.transition) {
+            (EventType::Deposit, _) => {
+                account.deposit(amount);
+                String::from("deposit")
+            }
+            (EventType::Withdrawal, _) => {
+                account.withdraw(amount);
+                String::from("withdrawal")
+            }
+            (EventType::Close, _) => {
+                account.close()?;
+                String::from("close")
+            }
+            (EventType::Lock, _) => {
+                account.lock();
+                String::from("lock")
+            }
+            (_, Some(transition)) => {
+                account.apply(transition, signed);
+                transition.event.to_string()
+            }
+            (event, None) => return Err(format!("Unknown dispute format: {:?}", event)),
+        };
+        // An operator lock names no transaction.
+        let tx = (entry.event != EventType::Lock).then_some(entry.tx);
+        lines.push(StatementLine::new(&name, tx, Some(amount), &account));
+    }
+
+    lines.push(StatementLine::new("closing", None, None, &account));
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::EngineCore, event::Event, processor::EventProcessor};
+    use rust_decimal::dec;
+
+    #[test]
+    fn test_statement_running_balances() {
+        let engine_core = EngineCore::default();
+        let processor = EventProcessor::new(engine_core.clone());
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 50.0),
+            Event::withdrawal(1, 3, 10.0),
+            Event::dispute(1, 3),
+            Event::deposit(2, 4, 5.0),
+        ];
+        for event in events {
+            let event = engine_core.ledger.add_event(event).unwrap().unwrap();
+            let _ = processor.process(&event);
+        }
+
+        let lines = build(&engine_core.ledger, 1).unwrap();
+        let summary: Vec<(&str, Option<u32>, Decimal, Decimal)> = lines
+            .iter()
+            .map(|line| (line.entry.as_str(), line.tx, line.available, line.held))
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("opening", None, dec!(0), dec!(0)),
+                ("deposit", Some(1), dec!(30), dec!(0)),
+                ("withdrawal", Some(3), dec!(20), dec!(0)),
+                ("dispute", Some(3), dec!(20), dec!(10)),
+                ("closing", None, dec!(20), dec!(10)),
+            ]
+        );
+        assert!(build(&engine_core.ledger, 3).is_err());
+    }
+
+    #[test]
+    fn test_statement_opens_with_carried_balances() {
+        let engine_core = EngineCore {
+            ledger: Ledger::default().with_dispute_window(1),
+            ..Default::default()
+        };
+        let processor = EventProcessor::new(engine_core.clone());
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 10.0),
+            Event::deposit(1, 3, 5.0),
+        ];
+        for event in events {
+            let event = engine_core.ledger.add_event(event).unwrap().unwrap();
+            let _ = processor.process(&event);
+        }
+
+        let lines = build(&engine_core.ledger, 1).unwrap();
+        let summary: Vec<(&str, Option<u32>, Decimal)> = lines
+            .iter()
+            .map(|line| (line.entry.as_str(), line.tx, line.available))
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("opening", None, dec!(20)),
+                ("deposit", Some(3), dec!(25)),
+                ("closing", None, dec!(25)),
+            ]
+        );
+    }
+}
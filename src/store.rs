@@ -0,0 +1,436 @@
+use dashmap::DashMap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt;
+
+use crate::{
+    account::Account,
+    journal::TrialBalance,
+    ledger::{DisputeRecord, LedgerEntry},
+};
+
+/// Where the ledger keeps its transactions and their dispute records, by ledger key.
+pub trait LedgerStore: fmt::Debug + Send + Sync {
+    /// Inserts the entry unless the key is taken, returning the entry already stored then.
+    fn insert_transaction(&self, key: u64, entry: LedgerEntry) -> Option<LedgerEntry>;
+
+    fn transaction(&self, key: u64) -> Option<LedgerEntry>;
+
+    /// Changes the stored entry and returns it as updated.
+    fn update_transaction(
+        &self,
+        key: u64,
+        update: &mut dyn FnMut(&mut LedgerEntry),
+    ) -> Option<LedgerEntry>;
+
+    fn remove_transaction(&self, key: u64) -> Option<LedgerEntry>;
+
+    fn disputes(&self, key: u64) -> Vec<DisputeRecord>;
+
+    fn push_dispute(&self, key: u64, record: DisputeRecord);
+
+    fn remove_disputes(&self, key: u64) -> Vec<DisputeRecord>;
+
+    fn transaction_keys(&self) -> Vec<u64>;
+
+    /// The keys of every transaction with dispute records.
+    fn disputed_keys(&self) -> Vec<u64>;
+
+    /// How many transactions and disputed transactions are stored.
+    #[cfg(test)]
+    fn counts(&self) -> (usize, usize);
+}
+
+/// Where the engine keeps the client accounts.
+pub trait AccountStore: fmt::Debug + Send + Sync {
+    fn account(&self, client: u16) -> Option<Account>;
+
+    /// Runs `action` on the client's account, opening the account first. The engine routes every
+    /// client to a single worker, so updates of one account never race.
+    fn update(
+        &self,
+        client: u16,
+        action: &mut dyn FnMut(&mut Account) -> Result<(), String>,
+    ) -> Result<(), String>;
+
+    /// Every account, by client.
+    fn accounts(&self) -> Vec<Account>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryLedger {
+    transactions: DashMap<u64, LedgerEntry>,
+    disputes: DashMap<u64, Vec<DisputeRecord>>,
+}
+
+impl LedgerStore for MemoryLedger {
+    fn insert_transaction(&self, key: u64, entry: LedgerEntry) -> Option<LedgerEntry> {
+        match self.transactions.entry(key) {
+            dashmap::mapref::entry::Entry::Occupied(stored) => Some(stored.get().clone()),
+            dashmap::mapref::entry::Entry::Vacant(vacant) => {
+                vacant.insert(entry);
+                None
+            }
+        }
+    }
+
+    fn transaction(&self, key: u64) -> Option<LedgerEntry> {
+        self.transactions.get(&key).map(|entry| entry.clone())
+    }
+
+    fn update_transaction(
+        &self,
+        key: u64,
+        update: &mut dyn FnMut(&mut LedgerEntry),
+    ) -> Option<LedgerEntry> {
+        self.transactions.get_mut(&key).map(|mut entry| {
+            update(&mut entry);
+            entry.clone()
+        })
+    }
+
+    fn remove_transaction(&self, key: u64) -> Option<LedgerEntry> {
+        self.transactions.remove(&key).map(|(_, entry)| entry)
+    }
+
+    fn disputes(&self, key: u64) -> Vec<DisputeRecord> {
+        self.disputes
+            .get(&key)
+            .map(|records| records.clone())
+            .unwrap_or_default()
+    }
+
+    fn push_dispute(&self, key: u64, record: DisputeRecord) {
+        self.disputes.entry(key).or_default().push(record);
+    }
+
+    fn remove_disputes(&self, key: u64) -> Vec<DisputeRecord> {
+        self.disputes
+            .remove(&key)
+            .map(|(_, records)| records)
+            .unwrap_or_default()
+    }
+
+    fn transaction_keys(&self) -> Vec<u64> {
+        self.transactions.iter().map(|entry| *entry.key()).collect()
+    }
+
+    fn disputed_keys(&self) -> Vec<u64> {
+        self.disputes.iter().map(|entry| *entry.key()).collect()
+    }
+
+    #[cfg(test)]
+    fn counts(&self) -> (usize, usize) {
+        (self.transactions.len(), self.disputes.len())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryChart {
+    accounts: DashMap<u16, Account>,
+}
+
+impl AccountStore for MemoryChart {
+    fn account(&self, client: u16) -> Option<Account> {
+        self.accounts.get(&client).map(|account| account.clone())
+    }
+
+    fn update(
+        &self,
+        client: u16,
+        action: &mut dyn FnMut(&mut Account) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut account = self
+            .accounts
+            .entry(client)
+            .or_insert_with(|| Account::new(client));
+        action(&mut account)
+    }
+
+    fn accounts(&self) -> Vec<Account> {
+        let mut accounts: Vec<Account> = self
+            .accounts
+            .iter()
+            .map(|account| account.clone())
+            .collect();
+        accounts.sort_by_key(Account::client);
+        accounts
+    }
+}
+
+/// An account as persisted: the trial balance of its journal, so every update writes a record of
+/// the same size.
+#[derive(Serialize, Deserialize)]
+struct StoredAccount {
+    trial_balance: TrialBalance,
+    locked: bool,
+    closed: bool,
+}
+
+/// Keeps the ledger and the chart in an embedded sled database, so they outlive the process.
+/// The books can't be kept consistent without their store, so storage errors are fatal.
+#[derive(Debug, Clone)]
+pub struct SledStore {
+    transactions: sled::Tree,
+    disputes: sled::Tree,
+    accounts: sled::Tree,
+}
+
+impl SledStore {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let fail = |e: sled::Error| format!("Can't open the store {}: {}", path, e);
+        Self::from_db(sled::open(path).map_err(fail)?).map_err(fail)
+    }
+
+    fn from_db(db: sled::Db) -> sled::Result<Self> {
+        Ok(SledStore {
+            transactions: db.open_tree("transactions")?,
+            disputes: db.open_tree("disputes")?,
+            accounts: db.open_tree("accounts")?,
+        })
+    }
+
+    /// Whether an earlier run left anything behind.
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty() && self.accounts.is_empty()
+    }
+
+    fn restore(client: u16, stored: StoredAccount) -> Account {
+        Account::from_trial_balance(client, stored.trial_balance, stored.locked, stored.closed)
+    }
+
+    fn read<T: DeserializeOwned>(bytes: &[u8]) -> T {
+        serde_json::from_slice(bytes).expect("Corrupt record in the store")
+    }
+
+    fn write<T: Serialize>(value: &T) -> Vec<u8> {
+        serde_json::to_vec(value).expect("Unserializable record for the store")
+    }
+
+    fn get<T: DeserializeOwned>(tree: &sled::Tree, key: &[u8]) -> Option<T> {
+        tree.get(key)
+            .expect("Can't read the store")
+            .map(|bytes| Self::read(&bytes))
+    }
+
+    fn keys(tree: &sled::Tree) -> Vec<u64> {
+        tree.iter()
+            .keys()
+            .map(|key| {
+                let key = key.expect("Can't read the store");
+                u64::from_be_bytes(key.as_ref().try_into().expect("Corrupt key in the store"))
+            })
+            .collect()
+    }
+
+    /// Reads, changes and writes back the value under `key`, again if another thread changed it
+    /// in the meantime.
+    fn update<T: Serialize + DeserializeOwned>(
+        tree: &sled::Tree,
+        key: &[u8],
+        mut update: impl FnMut(Option<T>) -> Option<T>,
+    ) -> Option<T> {
+        tree.update_and_fetch(key, |bytes| {
+            update(bytes.map(Self::read)).map(|value| Self::write(&value))
+        })
+        .expect("Can't write the store")
+        .map(|bytes| Self::read(&bytes))
+    }
+}
+
+impl LedgerStore for SledStore {
+    fn insert_transaction(&self, key: u64, entry: LedgerEntry) -> Option<LedgerEntry> {
+        self.transactions
+            .compare_and_swap(
+                key.to_be_bytes(),
+                None as Option<&[u8]>,
+                Some(Self::write(&entry)),
+            )
+            .expect("Can't write the store")
+            .err()
+            .and_then(|conflict| conflict.current)
+            .map(|bytes| Self::read(&bytes))
+    }
+
+    fn transaction(&self, key: u64) -> Option<LedgerEntry> {
+        Self::get(&self.transactions, &key.to_be_bytes())
+    }
+
+    fn update_transaction(
+        &self,
+        key: u64,
+        update: &mut dyn FnMut(&mut LedgerEntry),
+    ) -> Option<LedgerEntry> {
+        Self::update(&self.transactions, &key.to_be_bytes(), |entry| {
+            entry.map(|mut entry| {
+                update(&mut entry);
+                entry
+            })
+        })
+    }
+
+    fn remove_transaction(&self, key: u64) -> Option<LedgerEntry> {
+        self.transactions
+            .remove(key.to_be_bytes())
+            .expect("Can't write the store")
+            .map(|bytes| Self::read(&bytes))
+    }
+
+    fn disputes(&self, key: u64) -> Vec<DisputeRecord> {
+        Self::get(&self.disputes, &key.to_be_bytes()).unwrap_or_default()
+    }
+
+    fn push_dispute(&self, key: u64, record: DisputeRecord) {
+        Self::update(&self.disputes, &key.to_be_bytes(), |records| {
+            let mut records: Vec<DisputeRecord> = records.unwrap_or_default();
+            records.push(record.clone());
+            Some(records)
+        });
+    }
+
+    fn remove_disputes(&self, key: u64) -> Vec<DisputeRecord> {
+        self.disputes
+            .remove(key.to_be_bytes())
+            .expect("Can't write the store")
+            .map(|bytes| Self::read(&bytes))
+            .unwrap_or_default()
+    }
+
+    fn transaction_keys(&self) -> Vec<u64> {
+        Self::keys(&self.transactions)
+    }
+
+    fn disputed_keys(&self) -> Vec<u64> {
+        Self::keys(&self.disputes)
+    }
+
+    #[cfg(test)]
+    fn counts(&self) -> (usize, usize) {
+        (self.transactions.len(), self.disputes.len())
+    }
+}
+
+impl AccountStore for SledStore {
+    fn account(&self, client: u16) -> Option<Account> {
+        Self::get(&self.accounts, &client.to_be_bytes())
+            .map(|stored: StoredAccount| Self::restore(client, stored))
+    }
+
+    fn update(
+        &self,
+        client: u16,
+        action: &mut dyn FnMut(&mut Account) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let stored = self.account(client);
+        let mut account = stored.clone().unwrap_or_else(|| Account::new(client));
+        let result = action(&mut account);
+        if result.is_err() {
+            // A rejected action leaves the account as it was, though a new one is still opened.
+            account = stored.unwrap_or_else(|| Account::new(client));
+        }
+        let stored = StoredAccount {
+            trial_balance: account.trial_balance().clone(),
+            locked: account.locked(),
+            closed: account.closed(),
+        };
+        self.accounts
+            .insert(client.to_be_bytes(), Self::write(&stored))
+            .expect("Can't write the store");
+        result
+    }
+
+    fn accounts(&self) -> Vec<Account> {
+        self.accounts
+            .iter()
+            .map(|item| {
+                let (key, bytes) = item.expect("Can't read the store");
+                let client =
+                    u16::from_be_bytes(key.as_ref().try_into().expect("Corrupt key in the store"));
+                Self::restore(client, Self::read(&bytes))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        engine::EngineCore,
+        event::Event,
+        ledger::{Ledger, Transaction, TransactionStatus},
+        processor::EventProcessor,
+        verify,
+    };
+    use rust_decimal::dec;
+    use std::sync::Arc;
+
+    fn temporary() -> SledStore {
+        SledStore::from_db(sled::Config::new().temporary(true).open().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_sled_ledger_store() {
+        let store = temporary();
+        let deposit = LedgerEntry {
+            transaction: Transaction::deposit(dec!(10), 1),
+            status: TransactionStatus::Accepted,
+            disputed: dec!(0),
+            state: String::from("undisputed"),
+            touched: 1,
+            pending: 0,
+        };
+
+        assert!(store.is_empty());
+        assert_eq!(store.insert_transaction(1, deposit.clone()), None);
+        assert!(!store.is_empty());
+        assert_eq!(
+            store.insert_transaction(1, deposit.clone()),
+            Some(deposit.clone())
+        );
+        let updated = store
+            .update_transaction(1, &mut |entry| entry.status = TransactionStatus::Applied)
+            .unwrap();
+        assert_eq!(updated.status, TransactionStatus::Applied);
+        assert_eq!(store.transaction(1), Some(updated));
+        assert_eq!(store.update_transaction(2, &mut |_| {}), None);
+        assert_eq!(store.counts(), (1, 0));
+        assert!(store.remove_transaction(1).is_some());
+        assert_eq!(store.transaction(1), None);
+    }
+
+    #[test]
+    fn test_engine_on_sled_store() {
+        let store = temporary();
+        let engine_core = EngineCore {
+            ledger: Ledger::default().with_store(Arc::new(store.clone())),
+            chart: Arc::new(store.clone()),
+            ..Default::default()
+        };
+        let processor = EventProcessor::new(engine_core.clone());
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 10.0),
+            Event::dispute(1, 1).with_amount(5.0),
+            Event::deposit(2, 3, 7.0),
+            Event::dispute(2, 3),
+            Event::chargeback(2, 3),
+        ];
+        for event in events {
+            let event = engine_core.ledger.add_event(event).unwrap().unwrap();
+            processor.process(&event).unwrap();
+        }
+
+        let accounts = store.accounts();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].available(), dec!(15));
+        assert_eq!(accounts[0].held(), dec!(5));
+        assert!(accounts[1].locked());
+        assert_eq!(store.disputed_keys(), vec![1, 3]);
+        assert_eq!(store.disputes(1).len(), 1);
+        assert_eq!(
+            verify::reconcile(&engine_core.ledger, &store),
+            vec![],
+            "accounts read back from the store reconcile with the ledger"
+        );
+    }
+}
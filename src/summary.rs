@@ -0,0 +1,150 @@
+use rust_decimal::Decimal;
+
+use crate::{
+    journal::{Book, TrialBalance},
+    ledger::Ledger,
+    store::AccountStore,
+};
+
+/// System-wide totals for signing off a run.
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub total_deposits: Decimal,
+    pub total_withdrawals: Decimal,
+    pub total_payouts: Decimal,
+    pub total_held: Decimal,
+    pub total_charged_back: Decimal,
+    pub total_chargeback_reversals: Decimal,
+    pub locked_accounts: usize,
+    pub closed_accounts: usize,
+    /// Dispute events naming another client's transaction.
+    pub client_mismatches: u64,
+    /// Exact repeats of recorded transactions, skipped.
+    pub duplicate_events: u64,
+    /// Transactions evicted from memory after their dispute window.
+    pub evicted_transactions: u64,
+    pub net_liability: Decimal,
+    pub trial_balance: TrialBalance,
+}
+
+impl Summary {
+    pub fn build(ledger: &Ledger, chart: &dyn AccountStore) -> Self {
+        let mut summary = Summary::default();
+
+        for client in ledger.clients() {
+            let rollup = ledger.rollup(client);
+            summary.total_deposits += rollup.deposits;
+            summary.total_withdrawals += rollup.withdrawals;
+            summary.total_payouts += rollup.payouts;
+            summary.total_charged_back += rollup.charged_back;
+            summary.total_chargeback_reversals += rollup.chargeback_reversals;
+        }
+
+        summary.client_mismatches = ledger.mismatches();
+        summary.duplicate_events = ledger.duplicates();
+        summary.evicted_transactions = ledger.evicted();
+
+        for account in chart.accounts() {
+            summary.total_held += account.held();
+            summary.net_liability += account.total();
+            summary.locked_accounts += account.locked() as usize;
+            summary.closed_accounts += account.closed() as usize;
+            summary.trial_balance.merge(account.trial_balance());
+        }
+
+        summary
+    }
+
+    pub fn rows(&self) -> Vec<(String, String)> {
+        let mut rows = vec![
+            ("total_deposits", self.total_deposits.to_string()),
+            ("total_withdrawals", self.total_withdrawals.to_string()),
+            ("total_payouts", self.total_payouts.to_string()),
+            ("total_held", self.total_held.to_string()),
+            ("total_charged_back", self.total_charged_back.to_string()),
+            (
+                "total_chargeback_reversals",
+                self.total_chargeback_reversals.to_string(),
+            ),
+            ("locked_accounts", self.locked_accounts.to_string()),
+            ("closed_accounts", self.closed_accounts.to_string()),
+            ("client_mismatches", self.client_mismatches.to_string()),
+            ("duplicate_events", self.duplicate_events.to_string()),
+            (
+                "evicted_transactions",
+                self.evicted_transactions.to_string(),
+            ),
+            ("net_liability", self.net_liability.to_string()),
+        ]
+        .into_iter()
+        .map(|(metric, value)| (metric.to_string(), value))
+        .collect::<Vec<_>>();
+
+        for book in Book::ALL {
+            rows.push((
+                format!("{}_debits", book),
+                self.trial_balance.debits(book).to_string(),
+            ));
+            rows.push((
+                format!("{}_credits", book),
+                self.trial_balance.credits(book).to_string(),
+            ));
+        }
+        rows.push((
+            String::from("trial_balance"),
+            if self.trial_balance.is_balanced() {
+                String::from("balanced")
+            } else {
+                String::from("unbalanced")
+            },
+        ));
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::EngineCore, event::Event, processor::EventProcessor};
+    use rust_decimal::dec;
+
+    #[test]
+    fn test_summary_totals() {
+        let engine_core = EngineCore::default();
+        let processor = EventProcessor::new(engine_core.clone());
+        let events = vec![
+            Event::deposit(1, 1, 30.0),
+            Event::withdrawal(1, 2, 10.0),
+            Event::withdrawal(1, 3, 50.0),
+            Event::deposit(2, 4, 20.0),
+            Event::withdrawal(2, 5, 5.0),
+            Event::dispute(2, 5),
+            Event::chargeback(2, 5),
+            Event::deposit(3, 6, 7.0),
+            Event::dispute(3, 6),
+            Event::dispute(3, 5),
+            Event::deposit(1, 1, 30.0),
+        ];
+        for event in events {
+            if let Ok(Some(event)) = engine_core.ledger.add_event(event) {
+                let _ = processor.process(&event);
+            }
+        }
+
+        let summary = Summary::build(&engine_core.ledger, engine_core.chart.as_ref());
+
+        assert_eq!(summary.total_deposits, dec!(57));
+        assert_eq!(summary.total_withdrawals, dec!(15));
+        assert_eq!(summary.total_held, dec!(7));
+        assert_eq!(summary.total_charged_back, dec!(5));
+        assert_eq!(summary.locked_accounts, 1);
+        assert_eq!(summary.client_mismatches, 1);
+        assert_eq!(summary.duplicate_events, 1);
+        assert_eq!(summary.net_liability, dec!(42));
+        assert_eq!(
+            summary.trial_balance.balance(Book::External),
+            -summary.net_liability
+        );
+        assert!(summary.trial_balance.is_balanced());
+    }
+}
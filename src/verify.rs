@@ -0,0 +1,382 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    account::Account,
+    event::EventType,
+    ledger::{Ledger, Transaction, TransactionHistory, TransactionStatus},
+    lifecycle::{AccountChange, DisputeProfile, Effect, UNDISPUTED},
+    store::AccountStore,
+};
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Discrepancy {
+    pub client: u16,
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+    pub transactions: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Balances {
+    available: Decimal,
+    held: Decimal,
+    locked: bool,
+    closed: bool,
+}
+
+/// A client's books as the ledger's transaction records have them.
+#[derive(Debug, Default)]
+struct Books {
+    available: Decimal,
+    held: Decimal,
+    /// The transactions whose latest dispute transition locked the account.
+    locked_by: BTreeSet<u32>,
+    operator_lock: bool,
+    closed: bool,
+}
+
+impl Books {
+    /// Opens with the balances carried forward from the client's evicted transactions. Operator
+    /// locks name no transaction, so they're only found among the client's applied events.
+    fn opening(ledger: &Ledger, client: u16) -> Self {
+        let carried = ledger.carried(client).unwrap_or_default();
+        Books {
+            available: carried.available,
+            held: carried.held,
+            locked_by: carried.locked_by,
+            operator_lock: ledger
+                .client_entries(client)
+                .iter()
+                .any(|entry| entry.event == EventType::Lock),
+            closed: carried.closed,
+        }
+    }
+
+    /// Posts an applied transaction and then every dispute event recorded against it, taking
+    /// each through the profile again to find the account changes.
+    fn post(&mut self, history: &TransactionHistory, profile: &DisputeProfile) {
+        if matches!(
+            history.status,
+            TransactionStatus::Accepted | TransactionStatus::Rejected(_)
+        ) {
+            return;
+        }
+        let amount = history.transaction.amount();
+        let on_deposit = match history.transaction {
+            Transaction::Deposit { .. } => {
+                self.available += amount;
+                true
+            }
+            Transaction::Withdrawal { .. } => {
+                self.available -= amount;
+                false
+            }
+            Transaction::Payout { .. } => {
+                self.available -= amount;
+                self.closed = true;
+                false
+            }
+        };
+
+        let mut state = String::from(UNDISPUTED);
+        for record in &history.disputes {
+            let amount = record.amount;
+            // Funds disputed on a deposit come out of the available ones; on a withdrawal they
+            // already left the engine.
+            match (record.effect, on_deposit) {
+                (Effect::Hold, true) => {
+                    self.available -= amount;
+                    self.held += amount;
+                }
+                (Effect::Hold, false) => self.held += amount,
+                (Effect::Resolve, true) | (Effect::Reject, false) => self.held -= amount,
+                (Effect::Resolve, false) | (Effect::Reject, true) => {
+                    self.held -= amount;
+                    self.available += amount;
+                }
+                (Effect::Restore, true) => self.available -= amount,
+                (Effect::Restore, false) => self.available += amount,
+                (Effect::None, _) => {}
+            }
+
+            let change = profile
+                .transition(&state, &record.event)
+                .and_then(|transition| transition.account);
+            match change {
+                Some(AccountChange::Lock) => {
+                    self.locked_by.insert(history.id);
+                }
+                Some(AccountChange::Unlock) => {
+                    self.locked_by.remove(&history.id);
+                }
+                None => {}
+            }
+            state = record.state.clone();
+        }
+    }
+
+    fn balances(self) -> Balances {
+        Balances {
+            available: self.available,
+            held: self.held,
+            locked: self.operator_lock || !self.locked_by.is_empty(),
+            closed: self.closed,
+        }
+    }
+}
+
+/// Recomputes every account from the ledger's transaction records alone, without the account
+/// chart or the balances the index rolled up, and compares it with the chart.
+pub fn reconcile(ledger: &Ledger, chart: &dyn AccountStore) -> Vec<Discrepancy> {
+    let mut books: BTreeMap<u16, Books> = BTreeMap::new();
+    for history in ledger.transactions() {
+        let client = history.transaction.client();
+        books
+            .entry(client)
+            .or_insert_with(|| Books::opening(ledger, client))
+            .post(&history, ledger.profile());
+    }
+
+    let clients: BTreeSet<u16> = ledger
+        .clients()
+        .into_iter()
+        .chain(chart.accounts().iter().map(Account::client))
+        .chain(books.keys().copied())
+        .collect();
+
+    let mut discrepancies = vec![];
+    for client in clients {
+        let expected = books
+            .remove(&client)
+            .unwrap_or_else(|| Books::opening(ledger, client))
+            .balances();
+        let transactions = transactions(ledger, client);
+        let actual = chart
+            .account(client)
+            .map(|account| Balances {
+                available: account.available(),
+                held: account.held(),
+                locked: account.locked(),
+                closed: account.closed(),
+            })
+            .unwrap_or_default();
+
+        let mut report = |field: &'static str, mismatch: bool, expected: String, actual: String| {
+            if mismatch {
+                discrepancies.push(Discrepancy {
+                    client,
+                    field,
+                    expected,
+                    actual,
+                    transactions: transactions.clone(),
+                });
+            }
+        };
+        let (expected_total, actual_total) = (
+            expected.available + expected.held,
+            actual.available + actual.held,
+        );
+        report(
+            "available",
+            expected.available != actual.available,
+            expected.available.to_string(),
+            actual.available.to_string(),
+        );
+        report(
+            "held",
+            expected.held != actual.held,
+            expected.held.to_string(),
+            actual.held.to_string(),
+        );
+        report(
+            "total",
+            expected_total != actual_total,
+            expected_total.to_string(),
+            actual_total.to_string(),
+        );
+        report(
+            "locked",
+            expected.locked != actual.locked,
+            expected.locked.to_string(),
+            actual.locked.to_string(),
+        );
+        report(
+            "closed",
+            expected.closed != actual.closed,
+            expected.closed.to_string(),
+            actual.closed.to_string(),
+        );
+    }
+    discrepancies
+}
+
+/// The client's transactions in the order their events were applied.
+fn transactions(ledger: &Ledger, client: u16) -> String {
+    let mut transactions = vec![];
+    for entry in ledger.client_entries(client) {
+        if entry.event == EventType::Lock {
+            continue;
+        }
+        let tx = entry.tx.to_string();
+        if !transactions.contains(&tx) {
+            transactions.push(tx);
+        }
+    }
+
+    transactions.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::EngineCore, event::Event, processor::EventProcessor};
+    use rust_decimal::dec;
+
+    fn process_events(engine_core: &EngineCore, events: Vec<Event>) {
+        let processor = EventProcessor::new(engine_core.clone());
+        for event in events {
+            let event = engine_core.ledger.add_event(event).unwrap().unwrap();
+            let _ = processor.process(&event);
+        }
+    }
+
+    #[test]
+    fn test_reconcile_matches_chart() {
+        let engine_core = EngineCore::default();
+        process_events(
+            &engine_core,
+            vec![
+                Event::deposit(1, 1, 30.0),
+                Event::withdrawal(1, 2, 10.0),
+                Event::withdrawal(1, 3, 50.0),
+                Event::dispute(1, 1),
+                Event::chargeback(1, 1),
+                Event::deposit(2, 4, 20.0),
+                Event::withdrawal(2, 5, 5.0),
+                Event::dispute(2, 5),
+                Event::resolve(2, 5),
+                Event::close(2, 6),
+                Event::deposit(3, 7, 10.0),
+                Event::dispute(3, 7),
+                Event::chargeback(3, 7),
+                Event::chargeback_reversal(3, 7),
+            ],
+        );
+
+        assert_eq!(
+            reconcile(&engine_core.ledger, engine_core.chart.as_ref()),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_reconcile_after_eviction() {
+        let engine_core = EngineCore {
+            ledger: Ledger::default().with_dispute_window(2),
+            ..Default::default()
+        };
+        process_events(
+            &engine_core,
+            vec![
+                Event::deposit(1, 1, 30.0),
+                Event::withdrawal(1, 2, 10.0),
+                Event::dispute(1, 2),
+                Event::resolve(1, 2),
+                Event::deposit(2, 3, 20.0),
+                Event::dispute(2, 3),
+                Event::chargeback(2, 3),
+                Event::deposit(1, 4, 1.0),
+                Event::deposit(1, 5, 2.0),
+                Event::close(1, 6),
+                Event::deposit(3, 7, 4.0),
+                Event::deposit(3, 8, 4.0),
+            ],
+        );
+
+        assert!(engine_core.ledger.evicted() > 0);
+        assert!(engine_core.ledger.carried(1).is_some());
+        assert_eq!(
+            reconcile(&engine_core.ledger, engine_core.chart.as_ref()),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_reconcile_locks_and_partial_disputes() {
+        let engine_core = EngineCore::default();
+        process_events(
+            &engine_core,
+            vec![
+                Event::deposit(1, 1, 30.0),
+                Event::lock(1),
+                Event::deposit(2, 2, 20.0),
+                Event::dispute(2, 2).with_amount(5.0),
+                Event::resolve(2, 2).with_amount(2.0),
+                Event::chargeback(2, 2),
+            ],
+        );
+        assert_eq!(
+            reconcile(&engine_core.ledger, engine_core.chart.as_ref()),
+            vec![]
+        );
+
+        engine_core
+            .chart
+            .update(1, &mut |account| {
+                account.unlock();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            reconcile(&engine_core.ledger, engine_core.chart.as_ref()),
+            vec![Discrepancy {
+                client: 1,
+                field: "locked",
+                expected: String::from("true"),
+                actual: String::from("false"),
+                transactions: String::from("1"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_reconcile_reports_mismatch() {
+        let engine_core = EngineCore::default();
+        process_events(
+            &engine_core,
+            vec![Event::deposit(1, 1, 30.0), Event::deposit(1, 2, 5.0)],
+        );
+        engine_core
+            .chart
+            .update(1, &mut |account| {
+                account.withdraw(dec!(1));
+                Ok(())
+            })
+            .unwrap();
+
+        let discrepancies = reconcile(&engine_core.ledger, engine_core.chart.as_ref());
+
+        assert_eq!(
+            discrepancies,
+            vec![
+                Discrepancy {
+                    client: 1,
+                    field: "available",
+                    expected: String::from("35"),
+                    actual: String::from("34"),
+                    transactions: String::from("1 2"),
+                },
+                Discrepancy {
+                    client: 1,
+                    field: "total",
+                    expected: String::from("35"),
+                    actual: String::from("34"),
+                    transactions: String::from("1 2"),
+                },
+            ]
+        );
+    }
+}
@@ -1,9 +1,70 @@
-use crate::{engine::EngineCore, event::Event, processor::EventProcessor, QUEUE_CAPACITY};
+use crate::{
+    engine::{EngineCore, Outcome},
+    event::Event,
+    processor::EventProcessor,
+    QUEUE_CAPACITY,
+};
 use crossbeam::queue::ArrayQueue;
-use std::{sync::Arc, thread, time::Duration};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use tokio::sync::oneshot;
+
+/// An event queued for a worker, and whoever waits for its outcome. The threaded and the tokio
+/// engine queue the same jobs.
+pub struct Job {
+    pub event: Event,
+    /// Set for a repeated transaction, which is a no-op that only reports its outcome.
+    pub repeated: bool,
+    reply: Option<oneshot::Sender<Outcome>>,
+}
+
+impl Job {
+    pub fn new(event: Event) -> Self {
+        Job {
+            event,
+            repeated: false,
+            reply: None,
+        }
+    }
+
+    pub fn repeated(event: Event) -> Self {
+        Job {
+            repeated: true,
+            ..Job::new(event)
+        }
+    }
+
+    pub fn with_reply(self, reply: oneshot::Sender<Outcome>) -> Self {
+        Job {
+            reply: Some(reply),
+            ..self
+        }
+    }
+
+    /// Applies the event and answers whoever waits for it. Rejections are recorded in the
+    /// ledger and reported through the outcome.
+    pub fn run(self, processor: &EventProcessor) {
+        let result = if self.repeated {
+            processor.repeated(&self.event)
+        } else {
+            processor.process(&self.event)
+        };
+        if let Some(reply) = self.reply {
+            let _ = reply.send(processor.outcome(self.event.client, result));
+        }
+    }
+}
 
 pub struct Worker {
-    pub queue: Arc<ArrayQueue<Event>>,
+    pub queue: Arc<ArrayQueue<Job>>,
+    /// Events taken off the queue, applied or not.
+    pub processed: Arc<AtomicU64>,
     processor: EventProcessor,
 }
 
@@ -12,6 +73,7 @@ impl Worker {
         Worker {
             processor: EventProcessor::new(engine_core),
             queue: Arc::new(ArrayQueue::new(QUEUE_CAPACITY)),
+            processed: Arc::new(AtomicU64::new(0)),
         }
     }
 
@@ -21,7 +83,10 @@ impl Worker {
         thread::spawn(move || {
             while !self.processor.is_shutdown() || !self.queue.is_empty() {
                 match queue.pop() {
-                    Some(event) => self.processor.process(&event),
+                    Some(job) => {
+                        job.run(&self.processor);
+                        self.processed.fetch_add(1, Ordering::Relaxed);
+                    }
                     None => thread::sleep(Duration::from_millis(10)),
                 }
             }